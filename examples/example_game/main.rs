@@ -8,7 +8,7 @@ use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 //use termion::style;
 
-use rpgrs::battle::Battle;
+use rpgrs::battle::{Battle, BattleMode};
 use rpgrs::battlecli::BattleCLI;
 use rpgrs::common::*;
 use rpgrs::encyclopedia::CharacterEncyclopedia;
@@ -32,7 +32,7 @@ fn bcli_test<R: Read, W: Write>(stdin: R, stdout: W, ch_enc: &CharacterEncyclope
     baddies.add_clone(&IndexedOrLiteral::Index(102), ch_enc);
     baddies.add_clone(&IndexedOrLiteral::Index(101), ch_enc);
 
-    let mut battle = Battle::new(allies, baddies);
+    let battle = Battle::new(allies, baddies);
     let mut cli = BattleCLI {
         stdin: stdin.keys(),
         stdout,
@@ -50,6 +50,7 @@ fn bcli_test_boss<R: Read, W: Write>(stdin: R, stdout: W, ch_enc: &CharacterEncy
     baddies.add_clone(&IndexedOrLiteral::Index(103), ch_enc);
 
     let mut battle = Battle::new(allies, baddies);
+    battle.mode = BattleMode::Wait;
     let mut cli = BattleCLI {
         stdin: stdin.keys(),
         stdout,
@@ -72,8 +73,8 @@ fn easy_fight(phonebook: &CharacterEncyclopedia) {
 fn boss_fight(phonebook: &CharacterEncyclopedia) {
     let stdout = io::stdout();
     let stdout = stdout.lock();
-    let stdin = io::stdin();
-    let stdin = stdin.lock();
+    // The boss fights in real time, so input can't block
+    let stdin = termion::async_stdin();
 
     // We go to raw mode to make the control over the terminal more fine-grained.
     let stdout = stdout.into_raw_mode().unwrap();
@@ -34,6 +34,9 @@ pub struct Action {
     pub effects: Effects,
     #[serde(default = "Action::default_scope")]
     pub scope: Scope,
+    // Can be switched between one target and all of their side
+    #[serde(default)]
+    pub spread: bool,
     // A format string that may use {:actor}, {:target}, or {:targets}.
     #[serde(default = "Action::default_message")]
     message: String,
@@ -45,6 +48,7 @@ pub enum CharacterAction {
     Menu(ActionMenu),
     Literal(Action),
     UseItem,
+    Run,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -55,7 +59,11 @@ pub struct ActionMenu {
 impl ActionMenu {
     // Used for serde(default) in Character
     pub fn new() -> ActionMenu {
-        let ca = vec![CharacterAction::Index(0), CharacterAction::UseItem];
+        let ca = vec![
+            CharacterAction::Index(0),
+            CharacterAction::UseItem,
+            CharacterAction::Run,
+        ];
         ActionMenu {
             prompt: "ROOT".to_string(),
             options: ca,
@@ -78,6 +86,7 @@ impl ActionMenu {
                 CharacterAction::Menu(m) => m.prompt.clone(),
                 CharacterAction::Literal(a) => a.prompt(),
                 CharacterAction::UseItem => "Item".to_string(),
+                CharacterAction::Run => "Run".to_string(),
             });
         }
         pr
@@ -114,7 +123,7 @@ impl Action {
     }
     pub fn get_message(&self, actor: &str, target_names: &[Name]) -> String {
         let ntargets = target_names.len();
-        let mut targets = target_names[..ntargets - 1].join(", ");
+        let mut targets = target_names[..ntargets.saturating_sub(1)].join(", ");
         if ntargets == 1 {
             targets = target_names.get(0).unwrap().to_string();
         } else if ntargets == 2 {
@@ -166,6 +175,7 @@ mod tests {
             CharacterAction::Index(attack_id),
             CharacterAction::Menu(magic_menu),
             CharacterAction::UseItem,
+            CharacterAction::Run,
         ];
         let am = ActionMenu {
             prompt: "ROOT".to_string(),
@@ -176,6 +186,7 @@ mod tests {
         assert_eq!(prompts[0], "Attack");
         assert_eq!(prompts[1], "Magic");
         assert_eq!(prompts[2], "Item");
+        assert_eq!(prompts[3], "Run");
     }
 
     #[test]
@@ -186,9 +197,11 @@ mod tests {
             costs: Costs::new(),
             effects: Effects::new(),
             scope: Scope::All,
+            spread: false,
             message: String::from("TEST {:actor} {:targets} TEST"),
         };
         let actor = "A";
+        assert_eq!(act.get_message(actor, &[]), "TEST A  TEST");
         let mut targets: Vec<Name> = vec![String::from("1")];
         assert_eq!(act.get_message(actor, &targets), "TEST A 1 TEST");
         targets.push(String::from("2"));
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+
+/// How a Character takes Effects with a certain trait, e.g. weak to Fire.
+/// Ordered weakest to strongest, so a Fire Effect that's also Elemental
+/// does nothing to someone weak to Fire but immune to Elemental.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Affinity {
+    // Twice the damage or healing
+    Weak,
+    // Half of it
+    Resist,
+    Immune,
+    // Damage heals, and healing damages
+    Absorb,
+}
+
+// Effect traits to Affinities, e.g. {"Fire": "Weak"}
+pub type Affinities = HashMap<Name, Affinity>;
+
+impl Affinity {
+    /// Scales a hit's amount
+    pub fn apply(&self, amount: i32) -> i32 {
+        match self {
+            Affinity::Weak => amount.saturating_mul(2),
+            Affinity::Resist => amount / 2,
+            Affinity::Immune => 0,
+            Affinity::Absorb => amount.saturating_neg(),
+        }
+    }
+}
+
+impl fmt::Display for Affinity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Affinity::Weak => write!(f, "weak to"),
+            Affinity::Resist => write!(f, "resists"),
+            Affinity::Immune => write!(f, "immune to"),
+            Affinity::Absorb => write!(f, "absorbs"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_test() {
+        let affinities: Affinities =
+            serde_json::from_str(r#"{"Fire": "Weak", "Ice": "Absorb", "Elemental": "Immune"}"#)
+                .unwrap();
+        assert_eq!(affinities["Fire"].apply(15), 30);
+        assert_eq!(Affinity::Resist.apply(15), 7);
+        assert_eq!(affinities["Elemental"].apply(15), 0);
+        assert_eq!(affinities["Ice"].apply(15), -15);
+        assert_eq!(affinities["Ice"].apply(-15), 15);
+        assert!(affinities["Elemental"] > affinities["Fire"]);
+    }
+}
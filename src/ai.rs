@@ -0,0 +1,316 @@
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::character::Character;
+use crate::common::*;
+use crate::encyclopedia::ActionEncyclopedia;
+use crate::party::Party;
+use crate::stats::Stat;
+
+/// Something an AI Rule or Phase can check before it's used
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum Check {
+    // The actor's pool is at or below a percent of its maximum
+    SelfBelow {
+        pool: Name,
+        percent: Stat,
+    },
+    // The actor's pool is above a percent of its maximum
+    SelfAbove {
+        pool: Name,
+        percent: Stat,
+    },
+    // Any of the actor's allies who are up, including the actor
+    AllyBelow {
+        pool: Name,
+        percent: Stat,
+    },
+    // Any of the actor's enemies who are up
+    EnemyBelow {
+        pool: Name,
+        percent: Stat,
+    },
+    // The actor's own turn count, starting at 0, is offset mod every
+    Turn {
+        every: u32,
+        #[serde(default)]
+        offset: u32,
+    },
+    // No more than this many of the actor's allies are up, including the actor
+    AlliesUp(usize),
+}
+
+/// How to pick a single target from those in an Action's Scope
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub enum TargetRule {
+    #[default]
+    Random,
+    // Least of a pool left
+    Lowest(Name),
+    // Least of a pool left, as a percent of its maximum
+    LowestPercent(Name),
+    Highest(Name),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Rule {
+    pub action: IndexedOrLiteral<Action>,
+    #[serde(default)]
+    pub when: Vec<Check>,
+    #[serde(default = "Rule::default_weight")]
+    pub weight: u32,
+    // Only the highest priority Rules that can be used are rolled for
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default)]
+    pub target: TargetRule,
+}
+
+impl Rule {
+    fn default_weight() -> u32 {
+        1
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Phase {
+    #[serde(default)]
+    pub when: Vec<Check>,
+    pub rules: Vec<Rule>,
+}
+
+/// A Character's battle behavior. The first Phase whose checks all pass
+/// is played, so later phases of a fight (e.g. below half HP) go first.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AiScript {
+    pub phases: Vec<Phase>,
+}
+
+/// What an AiScript can see on its turn
+pub struct AiContext<'a> {
+    pub actor: &'a Character,
+    pub allies: &'a Party,
+    pub enemies: &'a Party,
+    // How many turns the actor has taken so far
+    pub turn: u32,
+}
+
+fn pool_percent(c: &Character, pool: &Name) -> Option<Stat> {
+    let (current, maximum) = c.get_pool_vals(pool.clone())?;
+    if maximum <= 0 {
+        return None;
+    }
+    Some(current * 100 / maximum)
+}
+
+fn up_members(party: &Party) -> impl Iterator<Item = (usize, &Character)> {
+    (0..party.len())
+        .filter_map(move |i| party.get_ch_by_pos(i).map(|c| (i, c)))
+        .filter(|(_, c)| !c.is_down())
+}
+
+impl Check {
+    pub fn passes(&self, ctx: &AiContext) -> bool {
+        let below = |c: &Character, pool: &Name, percent: Stat| {
+            pool_percent(c, pool).is_some_and(|p| p <= percent)
+        };
+        match self {
+            Check::SelfBelow { pool, percent } => below(ctx.actor, pool, *percent),
+            Check::SelfAbove { pool, percent } => {
+                pool_percent(ctx.actor, pool).is_some_and(|p| p > *percent)
+            }
+            Check::AllyBelow { pool, percent } => {
+                up_members(ctx.allies).any(|(_, c)| below(c, pool, *percent))
+            }
+            Check::EnemyBelow { pool, percent } => {
+                up_members(ctx.enemies).any(|(_, c)| below(c, pool, *percent))
+            }
+            Check::Turn { every, offset } => *every > 0 && ctx.turn % every == *offset,
+            Check::AlliesUp(n) => up_members(ctx.allies).count() <= *n,
+        }
+    }
+}
+
+impl TargetRule {
+    /// Picks the position of one up member of the party
+    pub fn pick<R: Rng>(&self, party: &Party, rng: &mut R) -> Option<usize> {
+        let up: Vec<(usize, &Character)> = up_members(party).collect();
+        self.pick_from(&up, rng)
+    }
+    /// Picks the key of one of the candidates, e.g. a position or PlayerIndex
+    pub fn pick_from<T: Clone, R: Rng>(
+        &self,
+        candidates: &[(T, &Character)],
+        rng: &mut R,
+    ) -> Option<T> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let current = |c: &Character, pool: &Name| c.get_pool_vals(pool.clone()).map(|v| v.0);
+        let by_pool = |value: &dyn Fn(&Character) -> Option<Stat>| {
+            candidates
+                .iter()
+                .filter_map(|(k, c)| value(c).map(|v| (k.clone(), v)))
+                .collect::<Vec<_>>()
+        };
+        match self {
+            TargetRule::Random => Some(candidates[rng.gen_range(0..candidates.len())].0.clone()),
+            TargetRule::Lowest(pool) => by_pool(&|c| current(c, pool))
+                .into_iter()
+                .min_by_key(|(_, v)| *v)
+                .map(|(k, _)| k),
+            TargetRule::LowestPercent(pool) => by_pool(&|c| pool_percent(c, pool))
+                .into_iter()
+                .min_by_key(|(_, v)| *v)
+                .map(|(k, _)| k),
+            TargetRule::Highest(pool) => by_pool(&|c| current(c, pool))
+                .into_iter()
+                .max_by_key(|(_, v)| *v)
+                .map(|(k, _)| k),
+        }
+    }
+}
+
+impl AiScript {
+    pub fn get_phase(&self, ctx: &AiContext) -> Option<&Phase> {
+        self.phases
+            .iter()
+            .find(|p| p.when.iter().all(|c| c.passes(ctx)))
+    }
+    /// Rolls for one of the usable Rules of the current Phase
+    pub fn choose<'a, R: Rng>(
+        &'a self,
+        ctx: &AiContext,
+        action_enc: &'a ActionEncyclopedia,
+        rng: &mut R,
+    ) -> Option<(&'a Rule, &'a Action)> {
+        let usable: Vec<(&Rule, &Action)> = self
+            .get_phase(ctx)?
+            .rules
+            .iter()
+            .filter(|r| r.weight > 0 && r.when.iter().all(|c| c.passes(ctx)))
+            .filter_map(|r| action_enc.resolve(&r.action).map(|a| (r, a)))
+            .filter(|(_, a)| ctx.actor.can_afford_action_costs(a))
+            .collect();
+        let priority = usable.iter().map(|(r, _)| r.priority).max()?;
+        let usable: Vec<_> = usable
+            .into_iter()
+            .filter(|(r, _)| r.priority == priority)
+            .collect();
+        let total: u32 = usable.iter().map(|(r, _)| r.weight).sum();
+        let mut roll = rng.gen_range(0..total);
+        for (r, a) in usable {
+            if roll < r.weight {
+                return Some((r, a));
+            }
+            roll -= r.weight;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn party(hps: &[i32]) -> Party {
+        let mut p = Party::new(String::from("Test"));
+        for (i, hp) in hps.iter().enumerate() {
+            p.add_character(Character::from_json(&format!(
+                r#"{{"id": {i}, "name": "C{i}",
+                    "pools": {{"HP": {{"name": "HP", "current": {hp}, "maximum": 100}},
+                              "MP": {{"name": "MP", "current": 10, "maximum": 10}}}}}}"#
+            )));
+        }
+        p
+    }
+
+    #[test]
+    fn check_test() {
+        let allies = party(&[80, 20]);
+        let enemies = party(&[50]);
+        let ctx = AiContext {
+            actor: allies.get_ch_by_pos(0).unwrap(),
+            allies: &allies,
+            enemies: &enemies,
+            turn: 4,
+        };
+        let hp = String::from("HP");
+        let check = |json: &str| serde_json::from_str::<Check>(json).unwrap().passes(&ctx);
+        assert!(!check(r#"{"SelfBelow": {"pool": "HP", "percent": 50}}"#));
+        assert!(check(r#"{"SelfAbove": {"pool": "HP", "percent": 50}}"#));
+        assert!(check(r#"{"AllyBelow": {"pool": "HP", "percent": 30}}"#));
+        assert!(!Check::EnemyBelow {
+            pool: hp,
+            percent: 30
+        }
+        .passes(&ctx));
+        assert!(check(r#"{"Turn": {"every": 2}}"#));
+        assert!(!check(r#"{"Turn": {"every": 3, "offset": 2}}"#));
+        assert!(!check(r#"{"AlliesUp": 1}"#));
+    }
+    #[test]
+    fn target_rule_test() {
+        let p = party(&[80, 20, 0, 60]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let hp = String::from("HP");
+        assert_eq!(TargetRule::Lowest(hp.clone()).pick(&p, &mut rng), Some(1));
+        assert_eq!(TargetRule::Highest(hp.clone()).pick(&p, &mut rng), Some(0));
+        assert_eq!(
+            TargetRule::Lowest(String::from("PP")).pick(&p, &mut rng),
+            None
+        );
+        for _ in 0..20 {
+            assert_ne!(TargetRule::Random.pick(&p, &mut rng), None);
+        }
+    }
+    #[test]
+    fn choose_test() {
+        let action_enc = ActionEncyclopedia::new("data/actions.json");
+        let script: AiScript = serde_json::from_str(
+            r#"{"phases": [
+                {"when": [{"SelfBelow": {"pool": "HP", "percent": 50}}],
+                 "rules": [{"action": {"Index": 61}}]},
+                {"rules": [
+                    {"action": {"Index": 1}, "weight": 3},
+                    {"action": {"Index": 8483}, "priority": 1, "target": {"LowestPercent": "HP"},
+                     "when": [{"AllyBelow": {"pool": "HP", "percent": 30}}]}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let enemies = party(&[100]);
+        let allies = party(&[100, 100]);
+        let ctx = AiContext {
+            actor: allies.get_ch_by_pos(0).unwrap(),
+            allies: &allies,
+            enemies: &enemies,
+            turn: 0,
+        };
+        let (_, a) = script.choose(&ctx, &action_enc, &mut rng).unwrap();
+        assert_eq!(a.copy_name(), "Attack");
+        // An ally in trouble takes priority, but only with MP to spare
+        let allies = party(&[100, 10]);
+        let ctx = AiContext {
+            actor: allies.get_ch_by_pos(0).unwrap(),
+            allies: &allies,
+            ..ctx
+        };
+        let (r, a) = script.choose(&ctx, &action_enc, &mut rng).unwrap();
+        assert_eq!(a.copy_name(), "Babe Boost");
+        assert_eq!(r.target.pick(&allies, &mut rng), Some(1));
+        // Bolt 2 in the last phase can't be afforded
+        let allies = party(&[40]);
+        let ctx = AiContext {
+            actor: allies.get_ch_by_pos(0).unwrap(),
+            allies: &allies,
+            ..ctx
+        };
+        assert!(script.choose(&ctx, &action_enc, &mut rng).is_none());
+    }
+}
@@ -1,22 +1,40 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
+use serde::{Deserialize, Serialize};
 use termion::event::Key;
 
 use crate::action::{Action, Costs, Scope};
+use crate::ai::{AiContext, TargetRule};
 use crate::character::Character;
 use crate::common::*;
-use crate::effect::Effect;
+use crate::condition::{ConditionTrigger, ConditionTriggers};
+use crate::effect::{Effect, Landing, Traits};
 use crate::encyclopedia::ActionEncyclopedia;
 use crate::encyclopedia::ConditionEncyclopedia;
 use crate::encyclopedia::EffectEncyclopedia;
+use crate::encyclopedia::ItemEncyclopedia;
 use crate::encyclopedia::StatBlockEncyclopedia;
+use crate::inventory::{Gold, Quantity};
+use crate::item::Item;
 use crate::party::Party;
+use crate::reaction::{Reaction, Response};
+use crate::stats::{Experience, Stat};
 
 pub mod battleui;
 use battleui::BattleUI;
+pub mod event;
+use event::{BattleEvent, EventQueue};
+pub mod headless;
+pub mod replay;
+use replay::Replay;
+pub mod turnorder;
+use turnorder::{Clocks, TurnOrder};
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum PlayerIndex {
     Ally(usize),
     Baddy(usize),
@@ -26,6 +44,8 @@ struct TargetedEffect {
     actor_pi: PlayerIndex,
     target_pi: PlayerIndex,
     effect: Effect,
+    // Set when played in reaction to another Effect
+    reaction: Option<Reaction>,
 }
 impl TargetedEffect {
     fn new(
@@ -38,79 +58,361 @@ impl TargetedEffect {
             actor_pi: actor_pi.clone(),
             target_pi: target_pi.clone(),
             effect: effect_enc.clone_entry(effect_iol).unwrap(),
+            reaction: None,
         }
     }
+    // Counters and the like go off once the Effect that set them off has landed
+    fn waits_for_hits(&self) -> bool {
+        self.reaction.as_ref().is_some_and(|r| !r.is_reflect())
+    }
 }
 struct TargetedHit {
     target_pi: PlayerIndex,
     pool: Name,
     amount: i32,
 }
+enum ConditionChange {
+    Inflict { condition_id: Id, chance: i32 },
+    Cure { traits: Traits },
+}
+struct TargetedCondition {
+    target_pi: PlayerIndex,
+    change: ConditionChange,
+}
+
+fn get_mut_ch<'a>(
+    allies: &'a mut Party,
+    baddies: &'a mut Party,
+    pi: &PlayerIndex,
+) -> &'a mut Character {
+    match pi {
+        PlayerIndex::Ally(i) => allies.get_mut_ch_by_pos(*i).unwrap(),
+        PlayerIndex::Baddy(i) => baddies.get_mut_ch_by_pos(*i).unwrap(),
+    }
+}
+
+/// How time passes in a Battle
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum BattleMode {
+    // Time stands still while a PC picks an action
+    #[default]
+    Turns,
+    // Active Time Battle: time keeps passing on a real-time tick, even in menus
+    Active,
+    // Like Active, but time stops while a PC is in a submenu or picking targets
+    Wait,
+}
+
+/// Real time between ATB ticks
+pub const ATB_TICK: Duration = Duration::from_millis(50);
+// Battle time that passes each ATB tick, so a turn comes up about every
+// 3.4 seconds at Speed 30
+const ATB_TICK_TIME: u16 = 32;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BattleOutcome {
+    Victory,
+    Defeat,
+    Fled,
+    Aborted,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Rewards {
+    pub experience: Experience,
+    pub gold: Gold,
+    pub items: Vec<(Id, Quantity)>,
+}
+
+/// How the PC's current selection of targets may change
+#[derive(Debug, Clone, Copy)]
+struct Targeting {
+    scope: Scope,
+    // Can switch between one target and their whole side
+    spread: bool,
+    // Downed Characters can be targeted
+    revives: bool,
+}
+
+impl Targeting {
+    fn is_group(scope: Scope) -> bool {
+        matches!(
+            scope,
+            Scope::MyAllies | Scope::Allies | Scope::Enemies | Scope::All
+        )
+    }
+}
+
+/// A PC's pick, saved to be played on their turn when the TurnOrder
+/// defers commands
+struct PendingCommand {
+    actor: PlayerIndex,
+    selections: Vec<usize>,
+    targets: Vec<PlayerIndex>,
+    // The Item picked, in case it's gone by then
+    item: Option<Id>,
+}
+
+/// What's left after a Battle, handed back to the game loop
+pub struct BattleResult {
+    pub outcome: BattleOutcome,
+    pub allies: Party,
+    pub baddies: Party,
+    pub rewards: Rewards,
+    pub turns: u32,
+    // Every key pressed during Battle::run
+    pub replay: Option<Replay>,
+}
 
 pub struct Battle {
     pub allies: Party,
     pub baddies: Party,
 
-    ended: bool,
+    outcome: Option<BattleOutcome>,
+    rewards: Rewards,
+    turns: u32,
+    // False for e.g. boss fights
+    pub can_escape: bool,
+    // Percent of the allies' gold dropped when they run away
+    pub escape_gold_penalty: Gold,
+    run_held: Stat,
+    pub mode: BattleMode,
+    turn_order: Box<dyn TurnOrder>,
+    pending: Vec<PendingCommand>,
+    // Turns taken by each Character, for AiScripts and Reactions
+    ally_turns: Vec<u32>,
+    baddy_turns: Vec<u32>,
+    // Every roll in the Battle comes from here, so a seed replays it exactly
+    seed: u64,
+    rng: RefCell<StdRng>,
     pub selections: Vec<usize>,
-    text: VecDeque<String>,
+    events: EventQueue,
     current_pc_idx: Option<PlayerIndex>,
     current_npc_idx: Option<PlayerIndex>,
     pub targets: Vec<PlayerIndex>,
+    targeting: Option<Targeting>,
     effects: VecDeque<TargetedEffect>,
     hits: VecDeque<TargetedHit>,
+    condition_changes: VecDeque<TargetedCondition>,
 
     action_enc: ActionEncyclopedia,
     condition_enc: ConditionEncyclopedia,
     effect_enc: EffectEncyclopedia,
+    item_enc: ItemEncyclopedia,
     statblocks: StatBlockEncyclopedia,
 }
 
 impl Battle {
     pub fn new(allies: Party, baddies: Party) -> Battle {
-        let mut text = VecDeque::<String>::new();
-        text.push_back("Battle start!".to_string());
+        Battle::with_seed(allies, baddies, rand::random())
+    }
+    pub fn with_seed(mut allies: Party, baddies: Party, seed: u64) -> Battle {
+        let mut events = EventQueue::new();
+        events.push(BattleEvent::BattleStarted);
+        // FIXME: references should be supplied by the top-level Game object
+        let item_enc = ItemEncyclopedia::new("data/items.json");
+        allies.stock_items(&item_enc);
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        if let Err((id, e)) = statblocks.check_stats() {
+            panic!("StatBlock {id} in data/stats.json: {e}");
+        }
+        let condition_enc = ConditionEncyclopedia::new("data/conditions.json");
+        let effect_enc = EffectEncyclopedia::new("data/effects.json");
+        if let Err((id, e)) = effect_enc.check_stats(&statblocks) {
+            panic!("Effect {id} in data/effects.json: {e}");
+        }
+        if let Err((id, name)) = effect_enc.check_conditions(&condition_enc) {
+            panic!("Effect {id} in data/effects.json inflicts a missing Condition, {name}");
+        }
         Battle {
             allies,
             baddies,
-            ended: false,
+            outcome: None,
+            rewards: Rewards::default(),
+            turns: 0,
+            can_escape: true,
+            escape_gold_penalty: 0,
+            run_held: 0,
+            mode: BattleMode::Turns,
+            turn_order: Box::new(Clocks),
+            pending: Vec::<PendingCommand>::new(),
+            ally_turns: Vec::<u32>::new(),
+            baddy_turns: Vec::<u32>::new(),
+            seed,
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
             selections: Vec::<usize>::new(),
-            text,
+            events,
             current_pc_idx: None,
             current_npc_idx: None,
             targets: Vec::<PlayerIndex>::new(),
+            targeting: None,
             effects: VecDeque::<TargetedEffect>::new(),
             hits: VecDeque::<TargetedHit>::new(),
+            condition_changes: VecDeque::<TargetedCondition>::new(),
             // FIXME: references should be supplied by the top-level Game object
             action_enc: ActionEncyclopedia::new("data/actions.json"),
-            condition_enc: ConditionEncyclopedia::new("data/conditions.json"),
-            effect_enc: EffectEncyclopedia::new("data/effects.json"),
-            statblocks: StatBlockEncyclopedia::new("data/stats.json"),
+            condition_enc,
+            effect_enc,
+            item_enc,
+            statblocks,
         }
     }
     fn next_turn(&mut self) {
         // Increment all characters' clocks while no one's turn is up
         loop {
-            if let Some(i) = self.allies.get_ready_ch_pos() {
-                self.current_pc_idx = Some(PlayerIndex::Ally(i));
-                self.selections.push(0);
-                let next = self.get_current_pc().unwrap().copy_name();
-                self.text.push_back(format!("It's {}'s turn!", next));
+            if self.start_ready_turn() {
+                return;
+            }
+            // Otherwise time passes in tick
+            if self.mode != BattleMode::Turns {
                 return;
             }
-            if let Some(i) = self.baddies.get_ready_ch_pos() {
-                self.current_npc_idx = Some(PlayerIndex::Baddy(i));
-                let next = self.get_current_npc().unwrap().copy_name();
-                self.text.push_back(format!("It's {}'s turn!", next));
+            if self.increment_clocks(1) {
+                return;
+            }
+        }
+    }
+    /// Starts the turn of whoever the TurnOrder says is up. Baddies can go
+    /// while a PC is picking an action in the ATB modes, but other PCs wait.
+    fn start_ready_turn(&mut self) -> bool {
+        let pcs_busy = self.current_pc_idx.is_some();
+        let Some(pi) = self.turn_order.take_ready(
+            &mut self.allies,
+            &mut self.baddies,
+            &self.statblocks,
+            pcs_busy,
+        ) else {
+            return false;
+        };
+        let name = self.get_ch_by_pi(&pi).copy_name();
+        self.events.push(BattleEvent::TurnStarted {
+            pi: pi.clone(),
+            name,
+        });
+        self.turns += 1;
+        match pi {
+            PlayerIndex::Ally(_) => {
+                self.current_pc_idx = Some(pi.clone());
+                // A pick left over from a round the PC went down in is stale
+                if self.turn_order.is_picking_turn() {
+                    self.pending.retain(|c| c.actor != pi);
+                }
+                match self.pending.iter().position(|c| c.actor == pi) {
+                    Some(i) => {
+                        let command = self.pending.remove(i);
+                        self.play_pending_command(command);
+                    }
+                    None => self.selections.push(0),
+                }
+            }
+            PlayerIndex::Baddy(_) => {
+                self.current_npc_idx = Some(pi);
                 self.play_npc_action();
                 self.current_npc_idx = None;
-                return;
             }
-            // Increment characters' clocks
-            self.allies
-                .increment_clocks(1, &self.condition_enc, &self.statblocks);
-            self.baddies
-                .increment_clocks(1, &self.condition_enc, &self.statblocks);
+        }
+        true
+    }
+    /// Returns whether any Conditions went off, which play out before
+    /// anyone's turn starts
+    fn increment_clocks(&mut self, dt: u16) -> bool {
+        self.turn_order
+            .pass_time(dt, &mut self.allies, &mut self.baddies, &self.statblocks);
+        let mut triggered = Vec::<(PlayerIndex, ConditionTriggers)>::new();
+        for (i, triggers) in self.allies.experience_conditions(dt, &self.condition_enc) {
+            triggered.push((PlayerIndex::Ally(i), triggers));
+        }
+        for (i, triggers) in self.baddies.experience_conditions(dt, &self.condition_enc) {
+            triggered.push((PlayerIndex::Baddy(i), triggers));
+        }
+        let any = !triggered.is_empty();
+        for (pi, triggers) in triggered {
+            self.queue_condition_triggers(pi, triggers);
+        }
+        any
+    }
+    /// Lets one ATB tick of battle time pass. Time stands still in Turns
+    /// mode, while messages are up, and in Wait mode while a PC is in a
+    /// submenu or picking targets.
+    pub fn tick(&mut self) {
+        if self.mode == BattleMode::Turns || self.outcome.is_some() || !self.events.is_empty() {
+            return;
+        }
+        if self.mode == BattleMode::Wait && self.is_pc_browsing() {
+            return;
+        }
+        if !self.increment_clocks(ATB_TICK_TIME) {
+            self.start_ready_turn();
+        }
+    }
+    fn is_pc_browsing(&self) -> bool {
+        self.current_pc_idx.is_some() && (self.selections.len() > 1 || self.targeting.is_some())
+    }
+    /// How close a Character is to their next turn, out of u16::MAX
+    pub fn get_gauge(&self, pi: &PlayerIndex) -> u16 {
+        self.turn_order.get_gauge(pi, &self.allies, &self.baddies)
+    }
+    /// Swaps out how turns come up. Best done before the Battle starts.
+    pub fn set_turn_order(&mut self, turn_order: Box<dyn TurnOrder>) {
+        self.turn_order = turn_order;
+    }
+    /// Forecasts the next n Characters to act from everyone's Speed and how
+    /// far along their turns are, without changing anything. Empty if the
+    /// TurnOrder can't tell.
+    pub fn predict_turns(&self, n: usize) -> Vec<PlayerIndex> {
+        self.turn_order
+            .predict(n, &self.allies, &self.baddies, &self.statblocks)
+    }
+    fn queue_condition_triggers(&mut self, pi: PlayerIndex, triggers: ConditionTriggers) {
+        let name = self.get_ch_by_pi(&pi).copy_name();
+        for trigger in triggers {
+            match trigger {
+                ConditionTrigger::Effect(condition_id, effect) => {
+                    let con = self.condition_enc.get(&condition_id).unwrap();
+                    self.events.push(BattleEvent::ConditionTriggered {
+                        target: pi.clone(),
+                        name: name.clone(),
+                        condition: con.copy_name(),
+                    });
+                    self.effects.push_back(TargetedEffect::new(
+                        &pi,
+                        &pi,
+                        &effect,
+                        &self.effect_enc,
+                    ));
+                }
+                ConditionTrigger::Hit(condition_id, hit) => {
+                    let con = self.condition_enc.get(&condition_id).unwrap();
+                    self.events.push(BattleEvent::ConditionTriggered {
+                        target: pi.clone(),
+                        name: name.clone(),
+                        condition: con.copy_name(),
+                    });
+                    let target = self.get_ch_by_pi(&pi);
+                    let amount = match &hit.amount {
+                        HitAmt::Constant(v) => *v,
+                        HitAmt::Formula(f) => f.eval_hit(
+                            Some(target),
+                            target,
+                            &self.statblocks,
+                            &mut *self.rng.borrow_mut(),
+                        ),
+                    };
+                    self.hits.push_back(TargetedHit {
+                        target_pi: pi.clone(),
+                        pool: hit.pool,
+                        amount,
+                    });
+                }
+                ConditionTrigger::Expired(condition_id) => {
+                    let con = self.condition_enc.get(&condition_id).unwrap();
+                    self.events.push(BattleEvent::ConditionExpired {
+                        target: pi.clone(),
+                        name: name.clone(),
+                        condition: con.copy_name(),
+                    });
+                }
+            }
         }
     }
     fn handle_hit(&mut self) {
@@ -120,27 +422,194 @@ impl Battle {
                 .unwrap()
                 .copy_name();
             let v = self
-                .get_mut_character(&Some(th.target_pi))
+                .get_mut_character(&Some(th.target_pi.clone()))
                 .unwrap()
                 .hit_pool(&th.pool, th.amount);
-            if v > 0 {
-                self.text
-                    .push_back(format!("{} took {} {} damage!", name, v, th.pool));
-            } else if v == 0 {
-                self.text.push_back(format!("No effect on {}...", name));
-            } else {
-                self.text
-                    .push_back(format!("{} was healed for {} {}!", name, -v, th.pool));
+            let target = th.target_pi.clone();
+            self.events.push(match v {
+                1.. => BattleEvent::Damage {
+                    target,
+                    name: name.clone(),
+                    pool: th.pool.clone(),
+                    amount: v,
+                },
+                0 => BattleEvent::NoEffect {
+                    target,
+                    name: name.clone(),
+                },
+                _ => BattleEvent::Heal {
+                    target,
+                    name: name.clone(),
+                    pool: th.pool.clone(),
+                    amount: -v,
+                },
+            });
+            // Running out of HP inflicts K.O. (or whatever the down Condition is)
+            let target = get_mut_ch(&mut self.allies, &mut self.baddies, &th.target_pi);
+            if target
+                .get_pool_vals(String::from("HP"))
+                .is_some_and(|hp| hp.0 <= 0)
+            {
+                if let Some(down_id) = self.condition_enc.find_down() {
+                    let con = self.condition_enc.get(&down_id).unwrap();
+                    if target.take_condition(down_id, con) {
+                        self.events.push(BattleEvent::CharacterDown {
+                            target: th.target_pi.clone(),
+                            name,
+                            condition: con.copy_name(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    fn handle_condition(&mut self) {
+        // Let pending damage land before Conditions are inflicted or cured
+        if !self.hits.is_empty() {
+            return;
+        }
+        if let Some(tc) = self.condition_changes.pop_front() {
+            let target = get_mut_ch(&mut self.allies, &mut self.baddies, &tc.target_pi);
+            let name = target.copy_name();
+            match tc.change {
+                ConditionChange::Inflict {
+                    condition_id,
+                    chance,
+                } => {
+                    let con = self
+                        .condition_enc
+                        .get(&condition_id)
+                        .expect("missing condition in encyclopedia");
+                    let (target_pi, condition) = (tc.target_pi.clone(), con.copy_name());
+                    self.events
+                        .push(if self.rng.borrow_mut().gen_range(0..100) >= chance {
+                            BattleEvent::ConditionAvoided {
+                                target: target_pi,
+                                name,
+                                condition,
+                            }
+                        } else if target.take_condition(condition_id, con) {
+                            BattleEvent::ConditionApplied {
+                                target: target_pi,
+                                name,
+                                condition,
+                            }
+                        } else {
+                            BattleEvent::ConditionAlreadyApplied {
+                                target: target_pi,
+                                name,
+                                condition,
+                            }
+                        });
+                }
+                ConditionChange::Cure { traits } => {
+                    for condition_id in target.cure_conditions(&traits, &self.condition_enc) {
+                        let con = self.condition_enc.get(&condition_id).unwrap();
+                        self.events.push(BattleEvent::ConditionCured {
+                            target: tc.target_pi.clone(),
+                            name: name.clone(),
+                            condition: con.copy_name(),
+                        });
+                    }
+                }
             }
         }
     }
     fn handle_effect(&mut self) {
+        if !self.hits.is_empty() && self.effects.front().is_some_and(|te| te.waits_for_hits()) {
+            return;
+        }
         if let Some(te) = self.effects.pop_front() {
+            if !self.play_reaction(&te) {
+                return self.handle_effect();
+            }
+            let te = self.react_to(te);
             let actor = self.get_ch_by_pi(&te.actor_pi);
             let target = self.get_ch_by_pi(&te.target_pi);
-            let hits = te
-                .effect
-                .actor_affect_target(actor, target, &self.statblocks);
+            let (landing, hits) = te.effect.actor_affect_target(
+                actor,
+                target,
+                &self.statblocks,
+                &mut *self.rng.borrow_mut(),
+            );
+            // A miss does nothing else, Conditions and all
+            if landing == Landing::Miss {
+                let name = target.copy_name();
+                self.events.push(BattleEvent::Missed {
+                    target: te.target_pi.clone(),
+                    name,
+                });
+                return;
+            }
+            let afflictions = te.effect.actor_afflict_target(
+                actor,
+                target,
+                &self.statblocks,
+                &mut *self.rng.borrow_mut(),
+            );
+            let target_name = target.copy_name();
+            let affinity = target
+                .get_affinity(&te.effect.traits)
+                .filter(|_| !hits.is_empty());
+            let scanned = te.effect.scan.then(|| target.get_affinities());
+            if landing == Landing::Critical {
+                self.events.push(BattleEvent::CriticalHit {
+                    target: te.target_pi.clone(),
+                    name: target_name.clone(),
+                });
+            }
+            if let Some(affinity) = affinity {
+                self.events.push(BattleEvent::AffinityHit {
+                    target: te.target_pi.clone(),
+                    name: target_name.clone(),
+                    affinity,
+                });
+            }
+            if let Some(affinities) = scanned {
+                self.events.push(BattleEvent::Scanned {
+                    target: te.target_pi.clone(),
+                    name: target_name.clone(),
+                    affinities,
+                });
+            }
+            for buff in &te.effect.buffs {
+                self.events.push(BattleEvent::StatChanged {
+                    target: te.target_pi.clone(),
+                    name: target_name.clone(),
+                    stat: buff.stat.clone(),
+                    boost: buff.mods.is_boost(),
+                });
+            }
+            if !te.effect.buffs.is_empty() {
+                let target = get_mut_ch(&mut self.allies, &mut self.baddies, &te.target_pi);
+                for buff in &te.effect.buffs {
+                    target.add_buff(buff.clone());
+                }
+            }
+            if !te.effect.traits.is_empty() {
+                self.condition_changes.push_back(TargetedCondition {
+                    target_pi: te.target_pi.clone(),
+                    change: ConditionChange::Cure {
+                        traits: te.effect.traits.clone(),
+                    },
+                });
+            }
+            for affliction in afflictions {
+                // The encyclopedia's Effects are checked when they're loaded,
+                // but a literal Effect could still name a missing Condition
+                let Some(condition_id) = self.condition_enc.find_by_name(&affliction.pool) else {
+                    continue;
+                };
+                if let HitAmt::Constant(chance) = affliction.amount {
+                    self.condition_changes.push_back(TargetedCondition {
+                        target_pi: te.target_pi.clone(),
+                        change: ConditionChange::Inflict {
+                            condition_id,
+                            chance,
+                        },
+                    });
+                }
+            }
             for hit in hits {
                 let target_pi = te.target_pi.clone();
                 let pool = hit.pool;
@@ -156,50 +625,182 @@ impl Battle {
             }
         }
     }
+    /// Sets off the target's reactions to an incoming Effect, handing back
+    /// the Effect to play, which is turned around if it was reflected
+    fn react_to(&mut self, te: TargetedEffect) -> TargetedEffect {
+        // Reactions can't set off reactions, so two counters can't loop forever
+        if te.reaction.is_some() || te.actor_pi == te.target_pi {
+            return te;
+        }
+        let target = self.get_ch_by_pi(&te.target_pi);
+        if target.is_down() {
+            return te;
+        }
+        let reactions: Vec<Reaction> = target
+            .get_reactions(&self.condition_enc)
+            .into_iter()
+            .filter(|r| r.is_set_off_by(&te.effect.traits))
+            .filter(|r| self.rng.borrow_mut().gen_range(0..100) < r.chance)
+            .cloned()
+            .collect();
+        let ctx = self.get_ai_context(&te.target_pi);
+        let reflect = reactions
+            .iter()
+            .find(|r| r.is_reflect() && r.when.iter().all(|c| c.passes(&ctx)))
+            .cloned();
+        if let Some(reflect) = reflect {
+            self.events.push(BattleEvent::Reflected {
+                pi: te.target_pi.clone(),
+                name: target.copy_name(),
+            });
+            return TargetedEffect {
+                actor_pi: te.actor_pi.clone(),
+                target_pi: te.actor_pi,
+                effect: te.effect,
+                reaction: Some(reflect),
+            };
+        }
+        for reaction in reactions {
+            let (effect, target_pi) = match &reaction.response {
+                Response::Counter(effect) => (effect, &te.actor_pi),
+                Response::Effect(effect) => (effect, &te.target_pi),
+                Response::Reflect => continue,
+            };
+            let mut reply = TargetedEffect::new(&te.target_pi, target_pi, effect, &self.effect_enc);
+            reply.reaction = Some(reaction);
+            self.effects.push_back(reply);
+        }
+        te
+    }
+    /// Whether a queued Effect still goes off. Counters and the like are
+    /// checked against the reactor now that the incoming Effect has landed.
+    fn play_reaction(&mut self, te: &TargetedEffect) -> bool {
+        let reaction = match &te.reaction {
+            Some(reaction) if !reaction.is_reflect() => reaction,
+            _ => return true,
+        };
+        let reactor = self.get_ch_by_pi(&te.actor_pi);
+        let countered = matches!(reaction.response, Response::Counter(_));
+        if reactor.is_down() || (countered && self.get_ch_by_pi(&te.target_pi).is_down()) {
+            return false;
+        }
+        let ctx = self.get_ai_context(&te.actor_pi);
+        if !reaction.when.iter().all(|c| c.passes(&ctx)) {
+            return false;
+        }
+        let (pi, name) = (te.actor_pi.clone(), reactor.copy_name());
+        self.events.push(if countered {
+            BattleEvent::Countered { pi, name }
+        } else {
+            let effect = String::from(te.effect.whoami().1);
+            BattleEvent::Reacted { pi, name, effect }
+        });
+        true
+    }
     fn get_current_pc_actions(&self) -> Vec<Vec<Name>> {
         if self.selections.is_empty() {
             return Vec::<Vec<Name>>::new();
         }
         let ns = self.selections.len() - 1;
         let parent_menu_selections = &self.selections[..ns];
+        let pc = match self.get_current_pc() {
+            Some(pc) => pc,
+            None => return Vec::<Vec<Name>>::new(), // fixme??
+        };
+        match pc.find_item_selection(parent_menu_selections) {
+            // The Item menu lists the Party's inventory instead of Actions
+            Some(depth) => {
+                let mut menus = pc.get_action_options(&self.selections[..depth], &self.action_enc);
+                menus.push(
+                    self.get_usable_items()
+                        .iter()
+                        .map(|(item, n)| format!("{}  x{}", item.copy_name(), n))
+                        .collect(),
+                );
+                menus
+            }
+            None => pc.get_action_options(parent_menu_selections, &self.action_enc),
+        }
+    }
+    fn get_pc_party(&self) -> &Party {
         match self.current_pc_idx {
-            Some(PlayerIndex::Ally(i)) => self
-                .allies
-                .get_ch_by_pos(i)
-                .unwrap()
-                .get_action_options(parent_menu_selections, &self.action_enc),
-            Some(PlayerIndex::Baddy(i)) => self
-                .baddies
-                .get_ch_by_pos(i)
-                .unwrap()
-                .get_action_options(parent_menu_selections, &self.action_enc),
-            None => Vec::<Vec<Name>>::new(), // fixme??
+            Some(PlayerIndex::Baddy(_)) => &self.baddies,
+            _ => &self.allies,
         }
     }
-    pub fn run(&mut self, ui: &mut dyn BattleUI) {
+    fn get_usable_items(&self) -> Vec<(&Item, Quantity)> {
+        self.get_pc_party()
+            .get_item_stacks()
+            .into_iter()
+            .filter(|(item, _)| item.is_usable())
+            .collect()
+    }
+    pub fn run(mut self, ui: &mut dyn BattleUI) -> BattleResult {
+        let mut replay = Replay::new(&self);
+        let mut notified = 0;
+        let mut next_tick = Instant::now() + ATB_TICK;
         loop {
-            ui.refresh(self);
-            let key = ui.get_key();
+            for event in &self.get_event_log()[notified..] {
+                ui.notify(event);
+            }
+            notified = self.get_event_log().len();
+            ui.refresh(&self);
+            let key = match self.mode {
+                BattleMode::Turns => Some(ui.get_key()),
+                // Time stops once the last message has been read
+                _ if self.outcome.is_some() && self.events.is_empty() => break,
+                _ => ui.poll_key(next_tick.saturating_duration_since(Instant::now())),
+            };
+            // No key came before the tick was due
+            let Some(key) = key else {
+                replay.record_tick();
+                self.tick();
+                next_tick += ATB_TICK;
+                continue;
+            };
+            replay.record(key);
             if key == Key::Char('q') {
+                self.outcome = Some(BattleOutcome::Aborted);
                 break;
             }
-            if let Key::Char(_c) = key {
-                // Collect it as entropy
-                //                self.rand.write_u8(c as u8);
-            }
             if self.handle_input(key) {
                 // Battle is over
                 break;
             }
         }
+        let mut result = self.into_result();
+        result.replay = Some(replay);
+        result
+    }
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn get_outcome(&self) -> Option<BattleOutcome> {
+        self.outcome
+    }
+    pub fn get_turns(&self) -> u32 {
+        self.turns
+    }
+    /// Ends the Battle, handing back the Parties so their HP, MP, and
+    /// Conditions carry over
+    pub fn into_result(self) -> BattleResult {
+        BattleResult {
+            outcome: self.outcome.unwrap_or(BattleOutcome::Aborted),
+            allies: self.allies,
+            baddies: self.baddies,
+            rewards: self.rewards,
+            turns: self.turns,
+            replay: None,
+        }
     }
     fn handle_input(&mut self, key: Key) -> bool {
-        if !self.text.is_empty() {
-            self.pop_text();
+        if !self.events.is_empty() {
+            self.pop_event();
             self.handle_effect();
             self.handle_hit();
+            self.handle_condition();
             if !self.check_end_game()
-                && self.text.is_empty()
+                && self.events.is_empty()
                 && self.current_pc_idx.is_none()
                 && self.current_npc_idx.is_none()
             {
@@ -207,17 +808,33 @@ impl Battle {
             }
             return false;
         }
-        self.make_selection(key);
-        self.ended
+        // Holding down R tries to run away without using a turn
+        if key == Key::Char('r') {
+            self.hold_run();
+        } else {
+            self.run_held = 0;
+            self.make_selection(key);
+        }
+        // Let the last messages be read before ending
+        self.outcome.is_some() && self.events.is_empty()
+    }
+    /// The BattleEvent currently being shown
+    pub fn get_event(&self) -> Option<&BattleEvent> {
+        self.events.front()
+    }
+    /// Every BattleEvent so far, including ones already shown
+    pub fn get_event_log(&self) -> &[BattleEvent] {
+        self.events.get_log()
     }
-    pub fn get_text(&self) -> Option<&String> {
-        self.text.front()
+    /// The current BattleEvent's default message
+    pub fn get_text(&self) -> Option<String> {
+        self.events.front().map(|e| e.to_string())
     }
-    fn pop_text(&mut self) -> Option<String> {
-        self.text.pop_front()
+    fn pop_event(&mut self) -> Option<BattleEvent> {
+        self.events.pop_front()
     }
     pub fn get_top_menu_options(&self) -> Option<Vec<String>> {
-        if !self.text.is_empty() {
+        if !self.events.is_empty() {
             return None; // todo
         }
         match self.current_pc_idx {
@@ -288,74 +905,331 @@ impl Battle {
                 }
                 costs = a.costs.clone();
                 // Queue the Action message
-                let msg = a.get_message(
+                let action = a.copy_name();
+                let message = a.get_message(
                     &self.get_current_pc().unwrap().copy_name(),
                     &self.get_target_names(),
                 );
-                self.text.push_back(msg);
+                self.events.push(BattleEvent::ActionUsed {
+                    actor: actor.clone(),
+                    action,
+                    targets: self.targets.clone(),
+                    message,
+                });
+            } else if let Some(item) = self.get_selected_item() {
+                let effect = self
+                    .effect_enc
+                    .clone_entry(item.get_effect().unwrap())
+                    .expect("missing effect in encyclopedia");
+                let (item_id, item_name) = item.whoami();
+                let item_name = String::from(item_name);
+                let actor_name = self.get_current_pc().unwrap().copy_name();
+                for target in &self.targets {
+                    let target_name = self.get_ch_by_pi(target).copy_name();
+                    self.events.push(BattleEvent::ItemUsed {
+                        actor: actor.clone(),
+                        item: item_name.clone(),
+                        target: target.clone(),
+                        message: effect.get_message(&actor_name, &target_name, &item_name),
+                    });
+                    teffects.push_back(TargetedEffect {
+                        actor_pi: actor.clone(),
+                        target_pi: target.clone(),
+                        effect: effect.clone(),
+                        reaction: None,
+                    });
+                }
+                // Using an Item uses it up
+                match actor {
+                    PlayerIndex::Ally(_) => self.allies.take_item(item_id),
+                    PlayerIndex::Baddy(_) => self.baddies.take_item(item_id),
+                };
             }
             let pi = self.current_pc_idx.clone();
             self.get_mut_character(&pi).unwrap().spend_costs(costs);
             self.effects.append(&mut teffects);
-            // Clear the menu stack
-            self.selections.clear();
-            self.current_pc_idx = None;
-            self.targets.clear();
+            self.end_pc_turn();
         }
     }
+    fn end_pc_turn(&mut self) {
+        if let Some(pi) = self.current_pc_idx.take() {
+            self.count_turn(&pi);
+        }
+        // Clear the menu stack
+        self.selections.clear();
+        self.targets.clear();
+        self.targeting = None;
+    }
+    // Saves the PC's pick to play on their turn later in the round
+    fn defer_pc_action(&mut self) {
+        let Some(actor) = self.current_pc_idx.clone() else {
+            return;
+        };
+        let item = self.get_selected_item();
+        let command = match self.get_selected_action() {
+            Some(a) => a.copy_name(),
+            None => item.map(|i| i.copy_name()).unwrap_or_default(),
+        };
+        self.pending.push(PendingCommand {
+            actor: actor.clone(),
+            selections: self.selections.clone(),
+            targets: self.targets.clone(),
+            item: item.map(|i| i.whoami().0),
+        });
+        let name = self.get_ch_by_pi(&actor).copy_name();
+        self.events.push(BattleEvent::CommandChosen {
+            pi: actor,
+            name,
+            command,
+        });
+        // Picking isn't a turn of its own; the command is counted when it plays
+        self.current_pc_idx = None;
+        self.end_pc_turn();
+    }
+    /// Plays a pick from earlier in the round. Targets who went down since
+    /// are swapped for others in the same Scope.
+    fn play_pending_command(&mut self, command: PendingCommand) {
+        self.selections = command.selections;
+        let targeting = if let Some(a) = self.get_selected_action() {
+            if !self.get_current_pc().unwrap().can_afford_action_costs(a) {
+                self.events.push(BattleEvent::CantAfford);
+                return self.end_pc_turn();
+            }
+            (a.scope, self.revives(&a.effects))
+        } else if let Some(item) = self.get_selected_item() {
+            if Some(item.whoami().0) != command.item {
+                self.events.push(BattleEvent::NoItems);
+                return self.end_pc_turn();
+            }
+            (item.scope, self.revives(item.get_effect()))
+        } else {
+            return self.end_pc_turn();
+        };
+        let (scope, revives) = targeting;
+        self.targets = command
+            .targets
+            .into_iter()
+            .filter(|pi| revives || !self.is_player_down(pi))
+            .collect();
+        if self.targets.is_empty() && scope != Scope::None {
+            let mut candidates = self.get_scope_targets(&command.actor, scope, revives);
+            if !Targeting::is_group(scope) {
+                candidates.truncate(1);
+            }
+            self.targets = candidates;
+        }
+        self.play_pc_action();
+    }
     fn get_selected_action(&self) -> Option<&Action> {
         match self.get_current_pc() {
             Some(c) => c.get_action_selection(&self.selections[..], &self.action_enc),
             None => None,
         }
     }
+    fn get_selected_item(&self) -> Option<&Item> {
+        let depth = self
+            .get_current_pc()?
+            .find_item_selection(&self.selections[..])?;
+        let i = self.selections.get(depth + 1)?;
+        self.get_usable_items().get(*i).map(|(item, _)| *item)
+    }
     fn next_menu(&mut self) {
         if let Some(a) = self.get_selected_action() {
             eprintln!("Targeting mode");
             // Enable targeting mode
-            // todo: check Action scope
             let actor = self.get_current_pc().unwrap();
             if actor.can_afford_action_costs(a) {
-                if a.scope == Scope::Enemy {
-                    self.targets = vec![PlayerIndex::Baddy(self.baddies.get_nth_up_pos(0))];
-                } else if a.scope == Scope::Ally {
-                    self.targets = vec![PlayerIndex::Ally(self.baddies.get_nth_up_pos(0))];
-                /*} else if a.scope == Scope::You {
-                if let PlayerIndex::Ally(mut i) = self.current_pc_idx {
-                    Battle::change_member_selection(Key::Right, &mut i, self.allies.len());
-                    self.targets = vec![PlayerIndex::Ally(i)];
-                }*/
-                } else if a.scope == Scope::Enemies {
-                    self.targets = (0..self.baddies.len())
-                        .map(PlayerIndex::Baddy)
-                        .collect::<Vec<_>>();
-                } else if a.scope == Scope::Allies {
-                    self.targets = (0..self.allies.len())
-                        .map(PlayerIndex::Ally)
-                        .collect::<Vec<_>>();
-                } else if a.scope == Scope::All {
-                    self.targets.append(
-                        &mut (0..self.baddies.len())
-                            .map(PlayerIndex::Baddy)
-                            .collect::<Vec<_>>(),
-                    );
-                    self.targets.append(
-                        &mut (0..self.allies.len())
-                            .map(PlayerIndex::Ally)
-                            .collect::<Vec<_>>(),
-                    );
-                } else {
-                    panic!("PC Action scopes other than Enemy, Ally, Enemies, Allies, and All not implemented yet.");
-                }
+                let targeting = Targeting {
+                    scope: a.scope,
+                    spread: a.spread,
+                    revives: self.revives(&a.effects),
+                };
+                self.start_targeting(targeting);
             } else {
-                self.text
-                    .push_back(String::from("Can't afford that action :-/"));
+                self.events.push(BattleEvent::CantAfford);
+            }
+        } else if let Some(item) = self.get_selected_item() {
+            let targeting = Targeting {
+                scope: item.scope,
+                spread: false,
+                revives: self.revives(item.get_effect()),
+            };
+            self.start_targeting(targeting);
+        } else if self
+            .get_current_pc()
+            .is_some_and(|pc| pc.is_run_selection(&self.selections[..]))
+        {
+            self.play_run();
+        } else if self
+            .get_current_pc()
+            .and_then(|pc| pc.find_item_selection(&self.selections[..]))
+            .is_some()
+        {
+            if self.get_usable_items().is_empty() {
+                self.events.push(BattleEvent::NoItems);
+            } else {
+                self.selections.push(0);
             }
         } else {
             eprintln!("Next menu");
             self.selections.push(0);
         }
     }
+    fn get_escape_chance(&self, runner_pi: &PlayerIndex) -> Stat {
+        let runner = self.get_ch_by_pi(runner_pi);
+        // Runners are up against the fastest enemy
+        let fastest = (0..self.baddies.len())
+            .filter_map(|i| self.baddies.get_ch_by_pos(i))
+            .filter(|ch| !ch.is_down())
+            .max_by_key(|ch| ch.get_stat_val(String::from("Speed"), 0, &self.statblocks));
+        match (runner.get_escape_formula(&self.statblocks), fastest) {
+            (_, None) => 100,
+            (None, Some(_)) => 50,
+            (Some(f), Some(enemy)) => f.eval_hit(
+                Some(runner),
+                enemy,
+                &self.statblocks,
+                &mut *self.rng.borrow_mut(),
+            ),
+        }
+    }
+    fn play_run(&mut self) {
+        if !self.can_escape {
+            self.events.push(BattleEvent::CantEscape);
+            return;
+        }
+        let runner = self.current_pc_idx.clone().unwrap();
+        let chance = self.get_escape_chance(&runner);
+        if self.rng.borrow_mut().gen_range(0..100) < chance {
+            self.escape();
+        } else {
+            self.events.push(BattleEvent::EscapeFailed);
+            self.end_pc_turn();
+        }
+    }
+    fn hold_run(&mut self) {
+        if !self.can_escape || self.outcome.is_some() {
+            return;
+        }
+        if let Some(runner) = self.current_pc_idx.clone() {
+            // Like FFVI, the longer R is held the likelier an escape
+            self.run_held += (self.get_escape_chance(&runner) / 4).max(1);
+            if self.run_held >= 100 {
+                self.escape();
+            }
+        }
+    }
+    fn escape(&mut self) {
+        self.reset();
+        self.targets.clear();
+        self.targeting = None;
+        self.run_held = 0;
+        self.events
+            .push(BattleEvent::BattleEnded(BattleOutcome::Fled));
+        let inventory = self.allies.get_mut_inventory();
+        let dropped = inventory
+            .get_gold()
+            .saturating_mul(self.escape_gold_penalty)
+            / 100;
+        if dropped > 0 {
+            inventory.spend_gold(dropped);
+            self.events.push(BattleEvent::GoldDropped(dropped));
+        }
+        self.outcome = Some(BattleOutcome::Fled);
+    }
+    /// Whether any of the Effects would bring a downed Character back
+    fn revives<'e>(&self, effects: impl IntoIterator<Item = &'e IndexedOrLiteral<Effect>>) -> bool {
+        let Some(down) = self
+            .condition_enc
+            .find_down()
+            .and_then(|id| self.condition_enc.get(&id))
+        else {
+            return false;
+        };
+        effects
+            .into_iter()
+            .filter_map(|e| self.effect_enc.resolve(e))
+            .any(|e| down.is_removed_by(&e.traits))
+    }
+    /// Everyone the actor could target with the Scope, enemies first
+    fn get_scope_targets(
+        &self,
+        actor_pi: &PlayerIndex,
+        scope: Scope,
+        revives: bool,
+    ) -> Vec<PlayerIndex> {
+        let allies = (0..self.allies.len()).map(PlayerIndex::Ally);
+        let baddies = (0..self.baddies.len()).map(PlayerIndex::Baddy);
+        let (own, other): (Vec<_>, Vec<_>) = match actor_pi {
+            PlayerIndex::Ally(_) => (allies.collect(), baddies.collect()),
+            PlayerIndex::Baddy(_) => (baddies.collect(), allies.collect()),
+        };
+        let targetable = |pi: &PlayerIndex| revives || !self.is_player_down(pi);
+        let own = own.into_iter().filter(targetable);
+        let other = other.into_iter().filter(targetable);
+        match scope {
+            Scope::None => vec![],
+            Scope::Me => vec![actor_pi.clone()],
+            Scope::You | Scope::MyAllies => own.filter(|pi| pi != actor_pi).collect(),
+            Scope::Ally | Scope::Allies => own.collect(),
+            Scope::Enemy | Scope::Enemies => other.collect(),
+            Scope::One | Scope::All => other.chain(own).collect(),
+        }
+    }
+    fn start_targeting(&mut self, targeting: Targeting) {
+        let actor_pi = self.current_pc_idx.clone().unwrap();
+        let candidates = self.get_scope_targets(&actor_pi, targeting.scope, targeting.revives);
+        self.targeting = Some(targeting);
+        if targeting.scope == Scope::None {
+            // Nothing to choose
+            self.targets.clear();
+            self.play_pc_action();
+        } else if candidates.is_empty() {
+            self.targeting = None;
+            self.events.push(BattleEvent::NoTargets);
+        } else if Targeting::is_group(targeting.scope) {
+            self.targets = candidates;
+        } else {
+            self.targets = vec![candidates[0].clone()];
+        }
+    }
+    fn move_targets(&mut self, key: Key) {
+        let (Some(targeting), Some(actor_pi)) = (self.targeting, self.current_pc_idx.clone())
+        else {
+            return;
+        };
+        if Targeting::is_group(targeting.scope) {
+            return;
+        }
+        let candidates = self.get_scope_targets(&actor_pi, targeting.scope, targeting.revives);
+        let first = self.targets[0].clone();
+        let is_baddy = |pi: &PlayerIndex| matches!(pi, PlayerIndex::Baddy(_));
+        let side: Vec<PlayerIndex> = candidates
+            .iter()
+            .filter(|pi| is_baddy(pi) == is_baddy(&first))
+            .cloned()
+            .collect();
+        let grouped = self.targets.len() > 1;
+        if (key == Key::Left || key == Key::Right) && !grouped {
+            let mut i = side.iter().position(|pi| *pi == first).unwrap_or(0);
+            Battle::change_member_selection(key, &mut i, side.len());
+            self.targets = vec![side[i].clone()];
+        } else if key == Key::Up || key == Key::Down {
+            // Up heads for the baddies and Down for the allies
+            let toward_baddies = key == Key::Up;
+            if grouped {
+                if is_baddy(&first) != toward_baddies {
+                    self.targets = vec![first];
+                }
+            } else if is_baddy(&first) == toward_baddies {
+                if targeting.spread {
+                    self.targets = side;
+                }
+            } else if let Some(pi) = candidates.iter().find(|pi| is_baddy(pi) == toward_baddies) {
+                self.targets = vec![pi.clone()];
+            }
+        }
+    }
     fn change_member_selection(key: Key, i: &mut usize, l: usize) {
         if key == Key::Left {
             if *i == 0 {
@@ -372,89 +1246,17 @@ impl Battle {
     }
     fn make_selection(&mut self, key: Key) {
         if !self.targets.is_empty() {
-            if let Key::Char(c) = key {
-                if c == '\n' {
+            if key == Key::Char('\n') {
+                if self.turn_order.defers_commands() {
+                    self.defer_pc_action();
+                } else {
                     self.play_pc_action();
                 }
-            } else if key == Key::Left || key == Key::Right {
-                if self.targets.len() < 2 {
-                    if let PlayerIndex::Ally(mut i) = self.targets.first().unwrap() {
-                        eprintln!("Selecting adjacent ally");
-                        self.targets.pop();
-                        Battle::change_member_selection(key, &mut i, self.allies.len());
-                        self.targets.push(PlayerIndex::Ally(i));
-                    } else if let PlayerIndex::Baddy(mut i) = self.targets.first().unwrap() {
-                        eprintln!("Selecting adjacent baddie");
-                        self.targets.pop();
-                        Battle::change_member_selection(key, &mut i, self.baddies.len());
-                        self.targets.push(PlayerIndex::Baddy(i));
-                    }
-                }
-            } else if key == Key::Up {
-                if matches!(self.targets.first(), Some(PlayerIndex::Baddy(_))) {
-                    if self.targets.len() > 1 {
-                        // Do nothing
-                        eprintln!("All baddies already selected");
-                    } else {
-                        /* todo check for something like Scope::OneOrAllEnemies
-                        eprintln!("Selecting all baddies");
-                        // Select all enemies
-                        for i in 0..self.baddies.len() {
-                            let b = PlayerIndex::Baddy(i);
-                            if !self.targets.contains(&b) {
-                                self.targets.push(b);
-                            }
-                        }*/
-                    }
-                } else if let PlayerIndex::Ally(mut i) = self.targets.first().unwrap() {
-                    /* todo check for something like Scope::OnePlayer etc.
-                    if self.targets.len() > 1 {
-                        eprintln!("Deselecting whole party");
-                        // Remove all but first element, the previous single-selected ally
-                        for _ in 1..self.targets.len() {
-                            self.targets.pop();
-                        }
-                    } else {
-                        // Move up to enemy party
-                        eprintln!("Moving selection to enemies");
-                        self.targets.pop(); // Remove the singular ally target
-                        if i >= self.baddies.len() { i = self.baddies.len()-1; }
-                        self.targets.push(PlayerIndex::Baddy(i));
-                    }*/
-                }
-            } else if key == Key::Down {
-                if let PlayerIndex::Baddy(mut i) = self.targets.first().unwrap() {
-                    /* ditto
-                    if self.targets.len() > 1 {
-                        eprintln!("Deselecting whole enemy party");
-                        // Remove all but first element, the previous single-selected baddy
-                        for _ in 1..self.targets.len() {
-                            self.targets.pop();
-                        }
-                    } else {
-                        // Move down to ally party
-                        eprintln!("Moving selection to allies");
-                        self.targets.pop(); // Remove the singlular enemy target
-                        if i >= self.allies.len() { i = self.allies.len()-1; }
-                        self.targets.push(PlayerIndex::Ally(i));
-                    }*/
-                } else if matches!(self.targets.first(), Some(PlayerIndex::Ally(_))) {
-                    if self.targets.len() > 1 {
-                        // Do nothing
-                    } else {
-                        /*
-                        // Select all allies
-                        eprintln!("Selecting all allies");
-                        for i in 0..self.allies.len() {
-                            let a = PlayerIndex::Ally(i);
-                            if !self.targets.contains(&a) {
-                                self.targets.push(a);
-                            }
-                        }*/
-                    }
-                }
             } else if key == Key::Esc {
                 self.targets.clear();
+                self.targeting = None;
+            } else {
+                self.move_targets(key);
             }
             return;
         }
@@ -499,48 +1301,105 @@ impl Battle {
             let mut costs = Costs::new();
             let mut teffects = VecDeque::<TargetedEffect>::new();
             let actor = self.get_current_npc().unwrap();
-            if let Some(a) = actor.get_random_action(&self.action_enc) {
-                let targets = self.get_random_targets(actor_pi, &a.scope);
+            if let Some((a, targets)) = self.choose_npc_action(actor_pi) {
                 let target_names = self._get_target_names(&targets);
-                for target_pi in targets {
+                for target_pi in &targets {
                     for effect in &a.effects {
-                        let te =
-                            TargetedEffect::new(actor_pi, &target_pi, effect, &self.effect_enc);
+                        let te = TargetedEffect::new(actor_pi, target_pi, effect, &self.effect_enc);
                         teffects.push_back(te);
                     }
                 }
                 costs = a.costs.clone();
                 // Queue the Action message
-                let msg = a.get_message(&actor.copy_name(), &target_names);
-                self.text.push_back(msg);
+                self.events.push(BattleEvent::ActionUsed {
+                    actor: actor_pi.clone(),
+                    action: a.copy_name(),
+                    targets: targets.clone(),
+                    message: a.get_message(&actor.copy_name(), &target_names),
+                });
             }
             let pi = self.current_npc_idx.clone();
+            if let Some(pi) = &pi {
+                self.count_turn(pi);
+            }
             self.get_mut_character(&pi).unwrap().spend_costs(costs);
             self.effects.append(&mut teffects);
             self.current_npc_idx = None;
         }
     }
-    fn get_random_targets(&self, actor_pi: &PlayerIndex, scope: &Scope) -> Vec<PlayerIndex> {
-        // todo Assuming actor_pi is PlayerIndex::Baddy
-        let na = self.allies.get_num_up();
-        let nb = self.baddies.get_num_up();
-        let mut rng = rand::thread_rng();
-        match scope {
-            Scope::Enemy => vec![PlayerIndex::Ally(
-                self.allies.get_nth_up_pos(rng.gen_range(0..na)),
-            )],
-            Scope::Ally => vec![PlayerIndex::Baddy(
-                self.baddies.get_nth_up_pos(rng.gen_range(0..nb)),
-            )],
-            Scope::Enemies => (0..na)
-                .map(|i| PlayerIndex::Ally(self.allies.get_nth_up_pos(i)))
-                .collect::<Vec<_>>(),
-            Scope::Allies => (0..nb)
-                .map(|i| PlayerIndex::Baddy(self.baddies.get_nth_up_pos(i)))
-                .collect::<Vec<_>>(),
-            _ => panic!("NPC Action scopes other than Enemy and Ally not implemented yet."),
+    /// What the Character can see for their AiScript or Reactions
+    fn get_ai_context(&self, pi: &PlayerIndex) -> AiContext<'_> {
+        let (allies, enemies) = match pi {
+            PlayerIndex::Ally(_) => (&self.allies, &self.baddies),
+            PlayerIndex::Baddy(_) => (&self.baddies, &self.allies),
+        };
+        AiContext {
+            actor: self.get_ch_by_pi(pi),
+            allies,
+            enemies,
+            turn: self.get_turn_count(pi),
         }
     }
+    fn count_turn(&mut self, pi: &PlayerIndex) {
+        let (counts, i) = match pi {
+            PlayerIndex::Ally(i) => (&mut self.ally_turns, *i),
+            PlayerIndex::Baddy(i) => (&mut self.baddy_turns, *i),
+        };
+        if counts.len() <= i {
+            counts.resize(i + 1, 0);
+        }
+        counts[i] += 1;
+    }
+    /// How many turns the Character has taken so far
+    fn get_turn_count(&self, pi: &PlayerIndex) -> u32 {
+        let (counts, i) = match pi {
+            PlayerIndex::Ally(i) => (&self.ally_turns, *i),
+            PlayerIndex::Baddy(i) => (&self.baddy_turns, *i),
+        };
+        counts.get(i).copied().unwrap_or(0)
+    }
+    /// Follows the actor's AiScript if they have one, or else picks at random
+    fn choose_npc_action(&self, actor_pi: &PlayerIndex) -> Option<(&Action, Vec<PlayerIndex>)> {
+        let actor = self.get_ch_by_pi(actor_pi);
+        let (action, target_rule) = match actor.get_ai() {
+            Some(ai) => {
+                let ctx = self.get_ai_context(actor_pi);
+                let (rule, a) = ai.choose(&ctx, &self.action_enc, &mut *self.rng.borrow_mut())?;
+                (a, rule.target.clone())
+            }
+            None => {
+                let a = actor.get_random_action(&self.action_enc, &mut *self.rng.borrow_mut())?;
+                (a, TargetRule::Random)
+            }
+        };
+        let targets = self.get_npc_targets(actor_pi, action, &target_rule);
+        if targets.is_empty() && action.scope != Scope::None {
+            return None;
+        }
+        Some((action, targets))
+    }
+    fn get_npc_targets(
+        &self,
+        actor_pi: &PlayerIndex,
+        action: &Action,
+        rule: &TargetRule,
+    ) -> Vec<PlayerIndex> {
+        let revives = self.revives(&action.effects);
+        let candidates = self.get_scope_targets(actor_pi, action.scope, revives);
+        if action.scope == Scope::None || Targeting::is_group(action.scope) {
+            return candidates;
+        }
+        let candidates: Vec<(PlayerIndex, &Character)> = candidates
+            .into_iter()
+            .map(|pi| {
+                let c = self.get_ch_by_pi(&pi);
+                (pi, c)
+            })
+            .collect();
+        rule.pick_from(&candidates, &mut *self.rng.borrow_mut())
+            .into_iter()
+            .collect()
+    }
     pub fn is_player_down(&self, pi: &PlayerIndex) -> bool {
         match pi {
             PlayerIndex::Ally(i) => self.allies.get_ch_by_pos(*i).unwrap().is_down(),
@@ -548,34 +1407,73 @@ impl Battle {
         }
     }
     fn reset(&mut self) {
-        //self.text.clear();
         self.current_npc_idx = None;
         self.current_pc_idx = None;
         self.selections.clear();
+        self.pending.clear();
         self.effects.clear();
         self.hits.clear();
+        self.condition_changes.clear();
     }
     pub fn check_end_game(&mut self) -> bool {
-        if self.ended {
+        if self.outcome.is_some() {
             return true;
         }
-        if self.effects.is_empty() && self.hits.is_empty() {
+        if self.effects.is_empty() && self.hits.is_empty() && self.condition_changes.is_empty() {
             if self.allies.all_down() {
                 self.reset();
-                self.text.push_back(String::from("LOooOoSER!"));
-                self.ended = true;
+                self.events
+                    .push(BattleEvent::BattleEnded(BattleOutcome::Defeat));
+                self.outcome = Some(BattleOutcome::Defeat);
             } else if self.baddies.all_down() {
                 self.reset();
-                self.text.push_back(String::from("A winner is you!"));
-                self.text.push_back(String::from(
-                    "You'd probably earn some experience points now.",
-                ));
-                self.text
-                    .push_back(String::from("You'd probably find some phat loot now."));
-                self.ended = true;
+                self.events
+                    .push(BattleEvent::BattleEnded(BattleOutcome::Victory));
+                self.award_experience();
+                self.award_loot();
+                self.outcome = Some(BattleOutcome::Victory);
             }
         }
-        self.ended
+        self.outcome.is_some()
+    }
+    fn award_experience(&mut self) {
+        let xp = self.baddies.get_experience_reward();
+        self.rewards.experience = xp;
+        for (pos, share, levels) in self.allies.share_experience(xp, &self.statblocks) {
+            let ch = self.allies.get_ch_by_pos(pos).unwrap();
+            self.events.push(BattleEvent::ExperienceGained {
+                name: ch.copy_name(),
+                amount: share,
+            });
+            if levels > 0 {
+                self.events.push(BattleEvent::LevelUp {
+                    name: ch.copy_name(),
+                    level: ch.get_level(),
+                });
+            }
+        }
+    }
+    fn award_loot(&mut self) {
+        let gold = self.baddies.get_gold_reward();
+        self.rewards.gold = gold;
+        if gold > 0 {
+            self.allies.get_mut_inventory().add_gold(gold);
+            self.events.push(BattleEvent::GoldFound(gold));
+        }
+        self.rewards.items = self.baddies.roll_drops(&mut *self.rng.borrow_mut());
+        for &(id, quantity) in &self.rewards.items {
+            let item = self
+                .item_enc
+                .clone_entry(&IndexedOrLiteral::Index(id))
+                .expect("missing item in encyclopedia");
+            let name = item.copy_name();
+            let stored = self.allies.add_item(item, quantity).is_ok();
+            self.events.push(BattleEvent::ItemFound {
+                item: name,
+                quantity,
+                stored,
+            });
+        }
     }
     pub fn is_pc_turn(&self, pi: &PlayerIndex) -> bool {
         match &self.current_pc_idx {
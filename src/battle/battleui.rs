@@ -1,8 +1,18 @@
+use std::time::Duration;
+
 use termion::event::Key;
 
+use crate::battle::event::BattleEvent;
 use crate::battle::Battle;
 
 pub trait BattleUI {
     fn refresh(&mut self, battle: &Battle);
     fn get_key(&mut self) -> Key;
+    /// Waits up to timeout for a key in the ATB BattleModes. None lets a
+    /// tick of battle time pass, so UIs that can't wait just block.
+    fn poll_key(&mut self, _timeout: Duration) -> Option<Key> {
+        Some(self.get_key())
+    }
+    /// Called with each new BattleEvent, before the next refresh
+    fn notify(&mut self, _event: &BattleEvent) {}
 }
@@ -0,0 +1,282 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::affinity::Affinity;
+use crate::battle::{BattleOutcome, PlayerIndex};
+use crate::common::Name;
+use crate::inventory::{Gold, Quantity};
+use crate::stats::{Experience, Stat};
+
+/// Everything that happens in a Battle, for UIs to render however they like.
+/// Display gives the default English messages.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BattleEvent {
+    BattleStarted,
+    TurnStarted {
+        pi: PlayerIndex,
+        name: Name,
+    },
+    // Picked to be played later in the round
+    CommandChosen {
+        pi: PlayerIndex,
+        name: Name,
+        command: Name,
+    },
+    // The message comes from the Action's or Effect's data
+    ActionUsed {
+        actor: PlayerIndex,
+        action: Name,
+        targets: Vec<PlayerIndex>,
+        message: String,
+    },
+    ItemUsed {
+        actor: PlayerIndex,
+        item: Name,
+        target: PlayerIndex,
+        message: String,
+    },
+    Damage {
+        target: PlayerIndex,
+        name: Name,
+        pool: Name,
+        amount: Stat,
+    },
+    Heal {
+        target: PlayerIndex,
+        name: Name,
+        pool: Name,
+        amount: Stat,
+    },
+    NoEffect {
+        target: PlayerIndex,
+        name: Name,
+    },
+    Missed {
+        target: PlayerIndex,
+        name: Name,
+    },
+    CriticalHit {
+        target: PlayerIndex,
+        name: Name,
+    },
+    // Hits scaled by the target's Affinity to the Effect's traits
+    AffinityHit {
+        target: PlayerIndex,
+        name: Name,
+        affinity: Affinity,
+    },
+    // Everything the target has an Affinity to
+    Scanned {
+        target: PlayerIndex,
+        name: Name,
+        affinities: Vec<(Name, Affinity)>,
+    },
+    StatChanged {
+        target: PlayerIndex,
+        name: Name,
+        stat: Name,
+        boost: bool,
+    },
+    ConditionApplied {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    ConditionAvoided {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    ConditionAlreadyApplied {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    ConditionCured {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    ConditionTriggered {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    ConditionExpired {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    // Afflicted with the down Condition, e.g. K.O.
+    CharacterDown {
+        target: PlayerIndex,
+        name: Name,
+        condition: Name,
+    },
+    // A Reaction going off
+    Countered {
+        pi: PlayerIndex,
+        name: Name,
+    },
+    Reflected {
+        pi: PlayerIndex,
+        name: Name,
+    },
+    Reacted {
+        pi: PlayerIndex,
+        name: Name,
+        effect: Name,
+    },
+    CantAfford,
+    NoTargets,
+    NoItems,
+    CantEscape,
+    EscapeFailed,
+    GoldDropped(Gold),
+    BattleEnded(BattleOutcome),
+    ExperienceGained {
+        name: Name,
+        amount: Experience,
+    },
+    LevelUp {
+        name: Name,
+        level: Stat,
+    },
+    GoldFound(Gold),
+    ItemFound {
+        item: Name,
+        quantity: Quantity,
+        // False if there was no room in the inventory
+        stored: bool,
+    },
+}
+
+impl fmt::Display for BattleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BattleEvent::*;
+        match self {
+            BattleStarted => write!(f, "Battle start!"),
+            TurnStarted { name, .. } => write!(f, "It's {name}'s turn!"),
+            CommandChosen { name, command, .. } => write!(f, "{name} gets ready to use {command}."),
+            ActionUsed { message, .. } | ItemUsed { message, .. } => write!(f, "{message}"),
+            Damage {
+                name, pool, amount, ..
+            } => write!(f, "{name} took {amount} {pool} damage!"),
+            Heal {
+                name, pool, amount, ..
+            } => write!(f, "{name} was healed for {amount} {pool}!"),
+            NoEffect { name, .. } => write!(f, "No effect on {name}..."),
+            Missed { name, .. } => write!(f, "Missed {name}!"),
+            CriticalHit { .. } => write!(f, "Critical hit!"),
+            AffinityHit { name, affinity, .. } => match affinity {
+                Affinity::Weak => write!(f, "It's super effective!"),
+                Affinity::Resist => write!(f, "It's not very effective..."),
+                Affinity::Immune => write!(f, "{name} is immune!"),
+                Affinity::Absorb => write!(f, "{name} absorbs it!"),
+            },
+            Scanned {
+                name, affinities, ..
+            } => {
+                if affinities.is_empty() {
+                    return write!(f, "{name} has no weaknesses or resistances.");
+                }
+                let affinities: Vec<String> = affinities
+                    .iter()
+                    .map(|(t, affinity)| format!("{affinity} {t}"))
+                    .collect();
+                write!(f, "{name}: {}.", affinities.join(", "))
+            }
+            StatChanged {
+                name, stat, boost, ..
+            } => {
+                let change = if *boost { "rose" } else { "fell" };
+                write!(f, "{name}'s {stat} {change}!")
+            }
+            ConditionApplied {
+                name, condition, ..
+            }
+            | CharacterDown {
+                name, condition, ..
+            } => write!(f, "{name} is afflicted with {condition}!"),
+            ConditionAvoided {
+                name, condition, ..
+            } => write!(f, "{name} avoided {condition}."),
+            ConditionAlreadyApplied {
+                name, condition, ..
+            } => write!(f, "{name} is already afflicted with {condition}."),
+            ConditionCured {
+                name, condition, ..
+            } => write!(f, "{name} was cured of {condition}!"),
+            ConditionTriggered {
+                name, condition, ..
+            } => write!(f, "{condition} takes effect on {name}!"),
+            ConditionExpired {
+                name, condition, ..
+            } => write!(f, "{name}'s {condition} wore off."),
+            Countered { name, .. } => write!(f, "{name} counterattacks!"),
+            Reflected { name, .. } => write!(f, "{name} reflected it back!"),
+            Reacted { name, effect, .. } => write!(f, "{name} reacted with {effect}!"),
+            CantAfford => write!(f, "Can't afford that action :-/"),
+            NoTargets => write!(f, "No one to target!"),
+            NoItems => write!(f, "No items to use!"),
+            CantEscape => write!(f, "Can't escape!"),
+            EscapeFailed => write!(f, "Couldn't escape!"),
+            GoldDropped(gold) => write!(f, "Dropped {gold} gold while escaping..."),
+            BattleEnded(BattleOutcome::Victory) => write!(f, "A winner is you!"),
+            BattleEnded(BattleOutcome::Defeat) => write!(f, "LOooOoSER!"),
+            BattleEnded(BattleOutcome::Fled) => write!(f, "Escaped!"),
+            BattleEnded(BattleOutcome::Aborted) => write!(f, "Battle aborted."),
+            ExperienceGained { name, amount } => write!(f, "{name} gained {amount} experience!"),
+            LevelUp { name, level } => write!(f, "{name} reached level {level}!"),
+            GoldFound(gold) => write!(f, "Found {gold} gold!"),
+            ItemFound {
+                item,
+                quantity,
+                stored,
+            } => {
+                let found = if *quantity > 1 {
+                    format!("{item} x{quantity}")
+                } else {
+                    item.clone()
+                };
+                if *stored {
+                    write!(f, "Found {found}!")
+                } else {
+                    write!(f, "Found {found}, but there's no room for it.")
+                }
+            }
+        }
+    }
+}
+
+/// BattleEvents waiting to be shown one at a time, plus a log of all of them
+#[derive(Debug, Default)]
+pub struct EventQueue {
+    pending: VecDeque<BattleEvent>,
+    log: Vec<BattleEvent>,
+}
+
+impl EventQueue {
+    pub fn new() -> EventQueue {
+        EventQueue::default()
+    }
+    pub fn push(&mut self, event: BattleEvent) {
+        self.log.push(event.clone());
+        self.pending.push_back(event);
+    }
+    pub fn front(&self) -> Option<&BattleEvent> {
+        self.pending.front()
+    }
+    pub fn back(&self) -> Option<&BattleEvent> {
+        self.pending.back()
+    }
+    pub fn pop_front(&mut self) -> Option<BattleEvent> {
+        self.pending.pop_front()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+    pub fn get_log(&self) -> &[BattleEvent] {
+        &self.log[..]
+    }
+}
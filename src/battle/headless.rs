@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use termion::event::Key;
+
+use crate::battle::battleui::BattleUI;
+use crate::battle::event::BattleEvent;
+use crate::battle::{Battle, PlayerIndex};
+use crate::common::Name;
+
+/// One step of a scripted fight
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Command {
+    // Pick a menu option by its prompt
+    Select(Name),
+    // Move the target cursor onto a Character and confirm
+    Target(PlayerIndex),
+    // Press a raw key, e.g. 'r' to hold Run
+    Press(Key),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScriptError(pub String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad battle script command: {}", self.0)
+    }
+}
+
+impl Error for ScriptError {}
+
+impl FromStr for Command {
+    type Err = ScriptError;
+    /// Parses "select Attack", "target Baddy(0)" or "press r"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ScriptError(String::from(s));
+        let (verb, arg) = s.trim().split_once(' ').ok_or_else(err)?;
+        let arg = arg.trim();
+        match verb {
+            "select" => Ok(Command::Select(String::from(arg))),
+            "target" => {
+                let (side, i) = arg
+                    .strip_suffix(')')
+                    .and_then(|a| a.split_once('('))
+                    .ok_or_else(err)?;
+                let i = i.parse::<usize>().map_err(|_| err())?;
+                match side {
+                    "Ally" => Ok(Command::Target(PlayerIndex::Ally(i))),
+                    "Baddy" => Ok(Command::Target(PlayerIndex::Baddy(i))),
+                    _ => Err(err()),
+                }
+            }
+            "press" => {
+                let mut chars = arg.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Command::Press(Key::Char(c))),
+                    _ => Err(err()),
+                }
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+/// A BattleUI that never touches a terminal. It clicks through messages,
+/// logging them and their BattleEvents, and plays Commands whenever a menu or target is up.
+/// Targets not given a Command are confirmed as they are, and the Battle
+/// is quit if the script runs out before it's over, or if a Command doesn't
+/// fit the screen, leaving a ScriptError for get_error. In the ATB modes it
+/// lets time pass whenever there's nothing else to do.
+pub struct HeadlessBattleUI {
+    commands: VecDeque<Command>,
+    log: Vec<String>,
+    events: Vec<BattleEvent>,
+    // None when there's nothing to do but wait for an ATB tick
+    next_key: Option<Key>,
+    moves: usize,
+    error: Option<ScriptError>,
+}
+
+impl HeadlessBattleUI {
+    pub fn new(commands: Vec<Command>) -> HeadlessBattleUI {
+        HeadlessBattleUI {
+            commands: VecDeque::from(commands),
+            log: Vec::<String>::new(),
+            events: Vec::<BattleEvent>::new(),
+            next_key: Some(Key::Char('q')),
+            moves: 0,
+            error: None,
+        }
+    }
+    /// Commands separated by commas or newlines
+    pub fn from_script(script: &str) -> Result<HeadlessBattleUI, ScriptError> {
+        let commands = script
+            .split([',', '\n'])
+            .filter(|c| !c.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(HeadlessBattleUI::new(commands))
+    }
+    pub fn get_log(&self) -> &[String] {
+        &self.log[..]
+    }
+    pub fn get_events(&self) -> &[BattleEvent] {
+        &self.events[..]
+    }
+    pub fn commands_left(&self) -> usize {
+        self.commands.len()
+    }
+    /// The Command that didn't fit the screen, if the script went wrong
+    pub fn get_error(&self) -> Option<&ScriptError> {
+        self.error.as_ref()
+    }
+    // Gives up on the script and quits the Battle
+    fn fail(&mut self, message: String) -> Key {
+        self.error = Some(ScriptError(message));
+        Key::Char('q')
+    }
+    fn choose_target(&mut self, battle: &Battle) -> Key {
+        let Some(Command::Target(pi)) = self.commands.front() else {
+            return Key::Char('\n');
+        };
+        if battle.targets.contains(pi) {
+            self.commands.pop_front();
+            self.moves = 0;
+            return Key::Char('\n');
+        }
+        self.moves += 1;
+        if self.moves > battle.allies.len() + battle.baddies.len() {
+            let message = format!("can't target {pi:?} from {:?}", battle.targets);
+            return self.fail(message);
+        }
+        Key::Right
+    }
+    fn choose_option(&mut self, battle: &Battle, options: &[String]) -> Key {
+        match self.commands.front() {
+            Some(Command::Select(name)) => {
+                let Some(i) = options
+                    .iter()
+                    .position(|o| o == name || o.split("  ").next() == Some(name))
+                else {
+                    let message = format!("no option {name} in {options:?}");
+                    return self.fail(message);
+                };
+                if battle.selections.last() == Some(&i) {
+                    self.commands.pop_front();
+                    Key::Char('\n')
+                } else {
+                    Key::Down
+                }
+            }
+            Some(Command::Press(key)) => {
+                let key = *key;
+                self.commands.pop_front();
+                key
+            }
+            Some(Command::Target(pi)) => {
+                let message = format!("can't target {pi:?} from a menu");
+                self.fail(message)
+            }
+            None => Key::Char('q'),
+        }
+    }
+}
+
+impl BattleUI for HeadlessBattleUI {
+    fn refresh(&mut self, battle: &Battle) {
+        self.next_key = if self.error.is_some() {
+            Some(Key::Char('q'))
+        } else if let Some(text) = battle.get_text() {
+            self.log.push(text);
+            Some(Key::Char('\n'))
+        } else if !battle.targets.is_empty() {
+            Some(self.choose_target(battle))
+        } else if let Some(options) = battle.get_top_menu_options() {
+            Some(self.choose_option(battle, &options))
+        } else if let Some(Command::Press(key)) = self.commands.front() {
+            let key = *key;
+            self.commands.pop_front();
+            Some(key)
+        } else {
+            None
+        };
+    }
+    fn get_key(&mut self) -> Key {
+        self.next_key.unwrap_or(Key::Char('\n'))
+    }
+    fn poll_key(&mut self, _timeout: Duration) -> Option<Key> {
+        self.next_key
+    }
+    fn notify(&mut self, event: &BattleEvent) {
+        self.events.push(event.clone());
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use termion::event::Key;
+
+use crate::battle::battleui::BattleUI;
+use crate::battle::turnorder::TurnSystem;
+use crate::battle::{Battle, BattleMode};
+use crate::inventory::Gold;
+use crate::party::Party;
+
+/// The subset of termion Keys a Battle responds to, plus ATB ticks
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReplayKey {
+    // No key came before an ATB tick was due
+    Tick,
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Esc,
+    Backspace,
+    Other,
+}
+
+impl From<Key> for ReplayKey {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Char(c) => ReplayKey::Char(c),
+            Key::Up => ReplayKey::Up,
+            Key::Down => ReplayKey::Down,
+            Key::Left => ReplayKey::Left,
+            Key::Right => ReplayKey::Right,
+            Key::Esc => ReplayKey::Esc,
+            Key::Backspace => ReplayKey::Backspace,
+            _ => ReplayKey::Other,
+        }
+    }
+}
+
+impl From<ReplayKey> for Key {
+    fn from(key: ReplayKey) -> Self {
+        match key {
+            ReplayKey::Tick => Key::Null,
+            ReplayKey::Char(c) => Key::Char(c),
+            ReplayKey::Up => Key::Up,
+            ReplayKey::Down => Key::Down,
+            ReplayKey::Left => Key::Left,
+            ReplayKey::Right => Key::Right,
+            ReplayKey::Esc => Key::Esc,
+            ReplayKey::Backspace => Key::Backspace,
+            ReplayKey::Other => Key::Null,
+        }
+    }
+}
+
+/// Everything needed to play a Battle again exactly as it went:
+/// the starting Parties and settings, the RNG seed and every key pressed.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Replay {
+    seed: u64,
+    allies: Party,
+    baddies: Party,
+    can_escape: bool,
+    escape_gold_penalty: Gold,
+    #[serde(default)]
+    mode: BattleMode,
+    // None for custom TurnOrders
+    #[serde(default)]
+    turn_system: Option<TurnSystem>,
+    keys: Vec<ReplayKey>,
+}
+
+impl Replay {
+    /// Should be taken before anything has rolled the Battle's RNG
+    pub fn new(battle: &Battle) -> Replay {
+        Replay {
+            seed: battle.seed,
+            allies: battle.allies.clone(),
+            baddies: battle.baddies.clone(),
+            can_escape: battle.can_escape,
+            escape_gold_penalty: battle.escape_gold_penalty,
+            mode: battle.mode,
+            turn_system: battle.turn_order.get_system(),
+            keys: Vec::<ReplayKey>::new(),
+        }
+    }
+    pub fn record(&mut self, key: Key) {
+        self.keys.push(ReplayKey::from(key));
+    }
+    pub fn record_tick(&mut self) {
+        self.keys.push(ReplayKey::Tick);
+    }
+    pub fn get_keys(&self) -> &[ReplayKey] {
+        &self.keys[..]
+    }
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn from_file(filename: &str) -> Result<Replay, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(filename)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+    pub fn to_file(&self, filename: &str) -> Result<(), Box<dyn Error>> {
+        let writer = BufWriter::new(File::create(filename)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+    /// Sets up the Battle again, along with a UI that presses the same keys.
+    /// Custom TurnOrders have to be set again before it's run.
+    pub fn into_battle(self) -> (Battle, ReplayUI) {
+        let mut battle = Battle::with_seed(self.allies, self.baddies, self.seed);
+        battle.can_escape = self.can_escape;
+        battle.escape_gold_penalty = self.escape_gold_penalty;
+        battle.mode = self.mode;
+        if let Some(turn_system) = self.turn_system {
+            battle.set_turn_order(turn_system.new_order());
+        }
+        (battle, ReplayUI::new(&self.keys))
+    }
+}
+
+/// A headless BattleUI that feeds recorded keys and ticks, then quits once
+/// they run out
+pub struct ReplayUI {
+    keys: VecDeque<ReplayKey>,
+}
+
+impl ReplayUI {
+    pub fn new(keys: &[ReplayKey]) -> ReplayUI {
+        ReplayUI {
+            keys: keys.iter().copied().collect(),
+        }
+    }
+}
+
+impl BattleUI for ReplayUI {
+    fn refresh(&mut self, _battle: &Battle) {}
+    fn get_key(&mut self) -> Key {
+        self.keys.pop_front().map_or(Key::Char('q'), Key::from)
+    }
+    fn poll_key(&mut self, _timeout: Duration) -> Option<Key> {
+        match self.keys.pop_front() {
+            Some(ReplayKey::Tick) => None,
+            key => Some(key.map_or(Key::Char('q'), Key::from)),
+        }
+    }
+}
@@ -28,9 +28,9 @@ fn new_test() {
     assert!(battle.get_mut_character(&None).is_none());
     assert!(battle.get_top_menu_options().is_none());
     assert!(battle.get_text().is_some());
-    assert!(battle.pop_text().is_some());
+    assert!(battle.pop_event().is_some());
     assert!(battle.get_text().is_none());
-    assert!(battle.pop_text().is_none());
+    assert!(battle.pop_event().is_none());
     assert!(battle.get_current_npc().is_none());
     assert!(battle.get_current_pc().is_none());
     //    assert!(!battle.get_current_pc_actions().is_empty());
@@ -71,7 +71,7 @@ fn get_character_test() {
 #[test]
 fn turn_start_test() {
     let mut battle = get_test_battle(1, 1);
-    while battle.pop_text().is_some() {}
+    while battle.pop_event().is_some() {}
     battle.force_turn(PlayerIndex::Ally(0));
     battle.selections.push(0);
     assert!(battle.get_current_pc().is_some());
@@ -97,22 +97,38 @@ fn handle_effect_test() {
         actor_pi: PlayerIndex::Ally(0),
         target_pi: PlayerIndex::Baddy(0),
         effect: effect_enc.get(&735730).unwrap().clone(),
+        reaction: None,
     });
     let mut battle = Battle {
         allies,
         baddies,
-        ended: false,
+        outcome: None,
+        rewards: Rewards::default(),
+        turns: 0,
+        can_escape: true,
+        escape_gold_penalty: 0,
+        run_held: 0,
+        mode: BattleMode::Turns,
+        turn_order: Box::new(Clocks),
+        pending: Vec::<PendingCommand>::new(),
+        ally_turns: Vec::<u32>::new(),
+        baddy_turns: Vec::<u32>::new(),
+        seed: 0,
+        rng: RefCell::new(StdRng::seed_from_u64(0)),
         selections: Vec::<usize>::new(),
-        text: VecDeque::<String>::new(),
+        events: EventQueue::new(),
         current_pc_idx: None,
         current_npc_idx: None,
         targets: Vec::<PlayerIndex>::new(),
+        targeting: None,
         effects,
         hits: VecDeque::<TargetedHit>::new(),
+        condition_changes: VecDeque::<TargetedCondition>::new(),
         // FIXME: references should be supplied by the top-level Game object
         action_enc: ActionEncyclopedia::new("data/actions.json"),
         condition_enc: ConditionEncyclopedia::new("data/conditions.json"),
         effect_enc: EffectEncyclopedia::new("data/effects.json"),
+        item_enc: ItemEncyclopedia::new("data/items.json"),
         statblocks: StatBlockEncyclopedia::new("data/stats.json"),
     };
     battle.handle_effect();
@@ -131,20 +147,1028 @@ fn handle_hit_test() {
     let mut battle = Battle {
         allies,
         baddies,
-        ended: false,
+        outcome: None,
+        rewards: Rewards::default(),
+        turns: 0,
+        can_escape: true,
+        escape_gold_penalty: 0,
+        run_held: 0,
+        mode: BattleMode::Turns,
+        turn_order: Box::new(Clocks),
+        pending: Vec::<PendingCommand>::new(),
+        ally_turns: Vec::<u32>::new(),
+        baddy_turns: Vec::<u32>::new(),
+        seed: 0,
+        rng: RefCell::new(StdRng::seed_from_u64(0)),
         selections: Vec::<usize>::new(),
-        text: VecDeque::<String>::new(),
+        events: EventQueue::new(),
         current_pc_idx: None,
         current_npc_idx: None,
         targets: Vec::<PlayerIndex>::new(),
+        targeting: None,
         effects: VecDeque::<TargetedEffect>::new(),
         hits,
+        condition_changes: VecDeque::<TargetedCondition>::new(),
         // FIXME: references should be supplied by the top-level Game object
         action_enc: ActionEncyclopedia::new("data/actions.json"),
         condition_enc: ConditionEncyclopedia::new("data/conditions.json"),
         effect_enc: EffectEncyclopedia::new("data/effects.json"),
+        item_enc: ItemEncyclopedia::new("data/items.json"),
         statblocks: StatBlockEncyclopedia::new("data/stats.json"),
     };
     battle.handle_hit();
-    assert!(!battle.text.is_empty());
+    assert!(!battle.events.is_empty());
+}
+
+#[test]
+fn inflict_and_cure_condition_test() {
+    let effect_enc = EffectEncyclopedia::new("data/effects.json");
+    let mut battle = get_test_battle(2, 2);
+    let poison_id = battle.condition_enc.find_by_name("Poison").unwrap();
+    // poison100
+    battle.effects.push_back(TargetedEffect {
+        actor_pi: PlayerIndex::Ally(0),
+        target_pi: PlayerIndex::Baddy(0),
+        effect: effect_enc.get(&50).unwrap().clone(),
+        reaction: None,
+    });
+    battle.handle_effect();
+    assert_eq!(battle.condition_changes.len(), 1);
+    battle.handle_condition();
+    assert!(battle.condition_changes.is_empty());
+    assert!(battle
+        .baddies
+        .get_ch_by_pos(0)
+        .unwrap()
+        .has_condition(poison_id));
+    // Antidote
+    battle.effects.push_back(TargetedEffect {
+        actor_pi: PlayerIndex::Ally(0),
+        target_pi: PlayerIndex::Baddy(0),
+        effect: effect_enc.get(&173002).unwrap().clone(),
+        reaction: None,
+    });
+    battle.handle_effect();
+    battle.handle_condition();
+    assert!(!battle
+        .baddies
+        .get_ch_by_pos(0)
+        .unwrap()
+        .has_condition(poison_id));
+    assert!(matches!(
+        battle.events.back(),
+        Some(BattleEvent::ConditionCured { condition, .. }) if condition == "Poison"
+    ));
+}
+
+#[test]
+fn unknown_condition_test() {
+    let mut battle = get_test_battle(1, 1);
+    let typo: Effect = serde_json::from_str(
+        r#"{"id": 999, "name": "Typo",
+            "conditions": [{"pool": "Posion", "amount": {"Constant": 100}}]}"#,
+    )
+    .unwrap();
+    let mut effects = EffectEncyclopedia::new("data/effects.json");
+    effects.en.insert(999, typo.clone());
+    assert_eq!(
+        effects.check_conditions(&battle.condition_enc),
+        Err((999, String::from("Posion")))
+    );
+    // Literal Effects skip it instead
+    battle.effects.push_back(TargetedEffect {
+        actor_pi: PlayerIndex::Ally(0),
+        target_pi: PlayerIndex::Baddy(0),
+        effect: typo,
+        reaction: None,
+    });
+    battle.handle_effect();
+    assert!(battle.condition_changes.is_empty());
+}
+
+#[test]
+fn knock_out_condition_test() {
+    let effect_enc = EffectEncyclopedia::new("data/effects.json");
+    let mut battle = get_test_battle(1, 1);
+    let ko_id = battle.condition_enc.find_down().unwrap();
+    battle.hits.push_back(TargetedHit {
+        target_pi: PlayerIndex::Baddy(0),
+        pool: String::from("HP"),
+        amount: 9999,
+    });
+    battle.handle_hit();
+    assert!(battle
+        .baddies
+        .get_ch_by_pos(0)
+        .unwrap()
+        .has_condition(ko_id));
+    assert!(battle.is_player_down(&PlayerIndex::Baddy(0)));
+    // Phoenix Down heals and removes K.O.
+    battle.effects.push_back(TargetedEffect {
+        actor_pi: PlayerIndex::Ally(0),
+        target_pi: PlayerIndex::Baddy(0),
+        effect: effect_enc.get(&173003).unwrap().clone(),
+        reaction: None,
+    });
+    battle.handle_effect();
+    battle.handle_hit();
+    battle.handle_condition();
+    assert!(!battle
+        .baddies
+        .get_ch_by_pos(0)
+        .unwrap()
+        .has_condition(ko_id));
+    assert!(!battle.is_player_down(&PlayerIndex::Baddy(0)));
+}
+
+#[test]
+fn repeat_hit_condition_test() {
+    let mut battle = get_test_battle(1, 1);
+    let poison_id = battle.condition_enc.find_by_name("Poison").unwrap();
+    let poison = battle.condition_enc.get(&poison_id).unwrap();
+    let ally = battle.allies.get_mut_ch_by_pos(0).unwrap();
+    ally.take_condition(poison_id, poison);
+    let hp = ally.get_pool_vals(String::from("HP")).unwrap().0;
+    while battle.hits.is_empty() {
+        battle.current_pc_idx = None;
+        battle.current_npc_idx = None;
+        battle.next_turn();
+    }
+    assert!(matches!(
+        battle.events.back(),
+        Some(BattleEvent::ConditionTriggered { target: PlayerIndex::Ally(0), condition, .. })
+            if condition == "Poison"
+    ));
+    battle.handle_hit();
+    let ally = battle.allies.get_ch_by_pos(0).unwrap();
+    assert_eq!(ally.get_pool_vals(String::from("HP")).unwrap().0, hp - 3);
+}
+
+#[test]
+fn buff_effect_test() {
+    let mut battle = get_test_battle(1, 1);
+    let effect: Effect = serde_json::from_str(
+        r#"{"id": 0, "buffs": [{"stat": "Offense", "AddMod": 5, "duration": 100}]}"#,
+    )
+    .unwrap();
+    let offense = battle.allies.get_ch_by_pos(0).unwrap().get_stat_val(
+        String::from("Offense"),
+        0,
+        &battle.statblocks,
+    );
+    battle.effects.push_back(TargetedEffect {
+        actor_pi: PlayerIndex::Ally(0),
+        target_pi: PlayerIndex::Ally(0),
+        effect,
+        reaction: None,
+    });
+    battle.handle_effect();
+    assert_eq!(
+        battle.events.back(),
+        Some(&BattleEvent::StatChanged {
+            target: PlayerIndex::Ally(0),
+            name: String::from("Test Dummy T1"),
+            stat: String::from("Offense"),
+            boost: true,
+        })
+    );
+    assert_eq!(
+        battle.events.back().unwrap().to_string(),
+        "Test Dummy T1's Offense rose!"
+    );
+    let ally = battle.allies.get_ch_by_pos(0).unwrap();
+    assert_eq!(
+        ally.get_stat_val(String::from("Offense"), 0, &battle.statblocks),
+        offense + 5
+    );
+}
+
+#[test]
+fn use_item_test() {
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let mut allies = Party::new(String::from("Allies"));
+    allies.add_clone(&IndexedOrLiteral::Index(10), &ch_enc); // Mog, with 2 Potions and an Elixer
+    let (_, baddies) = get_test_parties(0, 1);
+    let mut battle = Battle::new(allies, baddies);
+    while battle.pop_event().is_some() {}
+    let mog = battle.allies.get_mut_ch_by_pos(0).unwrap();
+    mog.hit_pool(&String::from("HP"), 50);
+    let hp = mog.get_pool_vals(String::from("HP")).unwrap().0;
+    battle.force_turn(PlayerIndex::Ally(0));
+    battle.selections.push(0);
+    battle.make_selection(Key::Char('4')); // Item
+    assert_eq!(
+        battle.get_top_menu_options().unwrap(),
+        vec!["Potion  x2", "Elixer  x1"]
+    );
+    battle.make_selection(Key::Char('\n'));
+    assert_eq!(battle.get_selected_item().unwrap().whoami(), (0, "Potion"));
+    assert_eq!(battle.targets, vec![PlayerIndex::Ally(0)]);
+    battle.make_selection(Key::Char('\n'));
+    assert_eq!(battle.get_text().unwrap(), "Mog used Potion on Mog.");
+    assert!(battle.current_pc_idx.is_none());
+    assert_eq!(battle.allies.get_item_stacks()[0].1, 1);
+    battle.handle_effect();
+    battle.handle_hit();
+    let mog = battle.allies.get_ch_by_pos(0).unwrap();
+    assert_eq!(mog.get_pool_vals(String::from("HP")).unwrap().0, hp + 50);
+}
+
+#[test]
+fn no_items_test() {
+    let mut allies = Party::new(String::from("Allies"));
+    allies.add_character(Character::new(0, String::from("Empty-handed")));
+    let (_, baddies) = get_test_parties(0, 1);
+    let mut battle = Battle::new(allies, baddies);
+    while battle.pop_event().is_some() {}
+    battle.force_turn(PlayerIndex::Ally(0));
+    battle.selections.push(0);
+    battle.make_selection(Key::Char('2')); // Item
+    assert_eq!(battle.selections, vec![1]);
+    assert_eq!(battle.get_text().unwrap(), "No items to use!");
+}
+
+#[test]
+fn victory_rewards_test() {
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let (allies, mut baddies) = get_test_parties(1, 0);
+    baddies.add_clone(&IndexedOrLiteral::Index(103), &ch_enc); // 420 experience
+    let mut battle = Battle::new(allies, baddies);
+    while battle.pop_event().is_some() {}
+    battle
+        .get_mut_character(&Some(PlayerIndex::Baddy(0)))
+        .unwrap()
+        .hit_pool(&String::from("HP"), 9999);
+    assert!(battle.check_end_game());
+    let events = battle.get_event_log();
+    assert!(events.contains(&BattleEvent::BattleEnded(BattleOutcome::Victory)));
+    assert!(events.contains(&BattleEvent::ExperienceGained {
+        name: String::from("Test Dummy T1"),
+        amount: 420,
+    }));
+    let text: Vec<String> = events.iter().map(|e| e.to_string()).collect();
+    assert!(text.contains(&String::from("Test Dummy T1 reached level 5!")));
+    let ally = battle.allies.get_ch_by_pos(0).unwrap();
+    assert_eq!(ally.get_level(), 5);
+    assert_eq!(ally.get_experience(), 420);
+    // Loot
+    assert!(text.contains(&String::from("Found 300 gold!")));
+    assert!(text.contains(&String::from("Found Elixer!")));
+    assert_eq!(battle.allies.get_inventory().get_gold(), 300);
+    assert_eq!(battle.allies.get_inventory().count(1), 1);
+    // Everything is handed back once the Battle is over
+    let result = battle.into_result();
+    assert_eq!(result.outcome, BattleOutcome::Victory);
+    assert_eq!(result.rewards.experience, 420);
+    assert_eq!(result.rewards.gold, 300);
+    assert!(result.rewards.items.contains(&(1, 1)));
+    assert_eq!(result.allies.get_ch_by_pos(0).unwrap().get_level(), 5);
+}
+
+#[test]
+fn defeat_outcome_test() {
+    let mut battle = get_test_battle(1, 1);
+    battle.next_turn();
+    assert_eq!(battle.get_turns(), 1);
+    assert!(battle.get_outcome().is_none());
+    battle
+        .get_mut_character(&Some(PlayerIndex::Ally(0)))
+        .unwrap()
+        .hit_pool(&String::from("HP"), 9999);
+    assert!(battle.check_end_game());
+    assert_eq!(battle.get_outcome(), Some(BattleOutcome::Defeat));
+    let result = battle.into_result();
+    assert_eq!(result.outcome, BattleOutcome::Defeat);
+    assert_eq!(result.rewards, Rewards::default());
+    assert_eq!(result.turns, 1);
+    assert!(result.allies.all_down());
+}
+
+struct QuitUI;
+impl BattleUI for QuitUI {
+    fn refresh(&mut self, _battle: &Battle) {}
+    fn get_key(&mut self) -> Key {
+        Key::Char('q')
+    }
+}
+
+#[test]
+fn aborted_outcome_test() {
+    let battle = get_test_battle(1, 1);
+    let result = battle.run(&mut QuitUI);
+    assert_eq!(result.outcome, BattleOutcome::Aborted);
+    assert_eq!(result.allies.len(), 1);
+    assert_eq!(result.baddies.len(), 1);
+}
+
+fn get_run_battle(speed: Stat) -> Battle {
+    // Mog, with Run on the menu and the given Speed
+    let json = std::fs::read_to_string("data/characters.json").unwrap();
+    let mut chars: serde_json::Value = serde_json::from_str(&json).unwrap();
+    chars["10"]["base_stats"]["Speed"] = serde_json::json!(speed);
+    let mog: Character = serde_json::from_value(chars["10"].take()).unwrap();
+    let mut allies = Party::new(String::from("Allies"));
+    allies.add_character(mog);
+    let (_, baddies) = get_test_parties(0, 1);
+    let mut battle = Battle::new(allies, baddies);
+    while battle.pop_event().is_some() {}
+    battle.force_turn(PlayerIndex::Ally(0));
+    battle.selections.push(0);
+    battle
+}
+
+#[test]
+fn run_test() {
+    let mut battle = get_run_battle(1000);
+    battle.make_selection(Key::Char('5')); // Run
+    assert_eq!(battle.get_text().unwrap(), "Escaped!");
+    assert_eq!(battle.get_outcome(), Some(BattleOutcome::Fled));
+    assert_eq!(battle.into_result().outcome, BattleOutcome::Fled);
+}
+
+#[test]
+fn cant_escape_test() {
+    let mut battle = get_run_battle(1000);
+    battle.can_escape = false;
+    battle.make_selection(Key::Char('5')); // Run
+    assert_eq!(battle.get_text().unwrap(), "Can't escape!");
+    assert!(battle.get_outcome().is_none());
+    assert_eq!(battle.current_pc_idx, Some(PlayerIndex::Ally(0)));
+}
+
+#[test]
+fn escape_gold_penalty_test() {
+    let mut battle = get_run_battle(1000);
+    battle.allies.get_mut_inventory().add_gold(500);
+    battle.escape_gold_penalty = 10;
+    battle.make_selection(Key::Char('5')); // Run
+    assert_eq!(
+        battle.pop_event().unwrap(),
+        BattleEvent::BattleEnded(BattleOutcome::Fled)
+    );
+    assert_eq!(
+        battle.get_text().unwrap(),
+        "Dropped 50 gold while escaping..."
+    );
+    assert_eq!(battle.allies.get_inventory().get_gold(), 450);
+    // Small purses still drop their share
+    let mut battle = get_run_battle(1000);
+    battle.allies.get_mut_inventory().add_gold(150);
+    battle.escape_gold_penalty = 10;
+    battle.make_selection(Key::Char('5')); // Run
+    battle.pop_event();
+    assert_eq!(battle.pop_event(), Some(BattleEvent::GoldDropped(15)));
+    assert_eq!(battle.allies.get_inventory().get_gold(), 135);
+}
+
+#[test]
+fn hold_run_test() {
+    // Slow runners still get away if R is held long enough
+    let mut battle = get_run_battle(0);
+    for _ in 0..100 {
+        if battle.handle_input(Key::Char('r')) || battle.get_outcome().is_some() {
+            break;
+        }
+    }
+    assert_eq!(battle.get_outcome(), Some(BattleOutcome::Fled));
+    assert_eq!(battle.get_text().unwrap(), "Escaped!");
+    // Still holding R, the battle ends once the messages have been read
+    assert!((0..10).any(|_| battle.handle_input(Key::Char('r'))));
+}
+
+#[test]
+fn seeded_battle_test() {
+    // Battles with the same seed and input play out exactly the same
+    let play = |seed| {
+        let (allies, baddies) = get_test_parties(1, 2);
+        let mut battle = Battle::with_seed(allies, baddies, seed);
+        assert_eq!(battle.get_seed(), seed);
+        let mut log = Vec::<String>::new();
+        for _ in 0..500 {
+            if let Some(text) = battle.get_text() {
+                log.push(text);
+            }
+            if battle.handle_input(Key::Char('\n')) {
+                break;
+            }
+        }
+        log
+    };
+    let log = play(42);
+    assert!(log.len() > 10);
+    assert_eq!(log, play(42));
+}
+
+struct ScriptUI(VecDeque<Key>);
+impl BattleUI for ScriptUI {
+    fn refresh(&mut self, _battle: &Battle) {}
+    fn get_key(&mut self) -> Key {
+        self.0.pop_front().unwrap_or(Key::Char('q'))
+    }
+}
+
+#[test]
+fn replay_test() {
+    let (allies, baddies) = get_test_parties(1, 2);
+    let battle = Battle::with_seed(allies, baddies, 7);
+    let mut keys = VecDeque::from(vec![Key::Char('\n'); 60]);
+    keys.push_back(Key::Down);
+    let result = battle.run(&mut ScriptUI(keys));
+    let replay = result.replay.unwrap();
+    assert_eq!(replay.get_seed(), 7);
+    let filename = std::env::temp_dir().join("rpgrs_replay_test.json");
+    let filename = filename.to_str().unwrap();
+    replay.to_file(filename).unwrap();
+    let replay = Replay::from_file(filename).unwrap();
+    std::fs::remove_file(filename).unwrap();
+    let keys = replay.get_keys().to_vec();
+    let (battle, mut ui) = replay.into_battle();
+    let replayed = battle.run(&mut ui);
+    assert_eq!(replayed.replay.unwrap().get_keys(), &keys[..]);
+    assert_eq!(replayed.outcome, result.outcome);
+    assert_eq!(replayed.turns, result.turns);
+    let hp = |party: &Party| {
+        (0..party.len())
+            .map(|i| {
+                party
+                    .get_ch_by_pos(i)
+                    .unwrap()
+                    .get_pool_vals(String::from("HP"))
+                    .unwrap()
+                    .0
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hp(&replayed.allies), hp(&result.allies));
+    assert_eq!(hp(&replayed.baddies), hp(&result.baddies));
+}
+
+#[test]
+fn script_command_test() {
+    use headless::Command;
+    assert_eq!(
+        "select Attack".parse(),
+        Ok(Command::Select(String::from("Attack")))
+    );
+    assert_eq!(
+        " target Baddy(1)".parse(),
+        Ok(Command::Target(PlayerIndex::Baddy(1)))
+    );
+    assert_eq!("press r".parse(), Ok(Command::Press(Key::Char('r'))));
+    assert!("target Boss(0)".parse::<Command>().is_err());
+    assert!("press rr".parse::<Command>().is_err());
+    assert!("select".parse::<Command>().is_err());
+}
+
+#[test]
+fn script_error_test() {
+    let (allies, baddies) = get_test_parties(1, 1);
+    let battle = Battle::with_seed(allies, baddies, 0);
+    let mut ui = headless::HeadlessBattleUI::from_script("select Dance").unwrap();
+    let result = battle.run(&mut ui);
+    assert_eq!(result.outcome, BattleOutcome::Aborted);
+    assert!(ui.get_error().unwrap().0.starts_with("no option Dance"));
+    let (allies, baddies) = get_test_parties(1, 1);
+    let battle = Battle::with_seed(allies, baddies, 0);
+    let mut ui = headless::HeadlessBattleUI::from_script("target Baddy(0)").unwrap();
+    battle.run(&mut ui);
+    assert_eq!(
+        ui.get_error().unwrap().0,
+        "can't target Baddy(0) from a menu"
+    );
+    let (allies, baddies) = get_test_parties(1, 1);
+    let battle = Battle::with_seed(allies, baddies, 0);
+    let mut ui = headless::HeadlessBattleUI::from_script("select Attack, target Baddy(5)").unwrap();
+    battle.run(&mut ui);
+    assert!(ui
+        .get_error()
+        .unwrap()
+        .0
+        .starts_with("can't target Baddy(5)"));
+}
+
+#[test]
+fn npc_ai_test() {
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let (allies, mut baddies) = get_test_parties(2, 0);
+    baddies.add_clone(&IndexedOrLiteral::Index(103), &ch_enc); // Casts Bolt 2 every third turn
+    let mut battle = Battle::with_seed(allies, baddies, 0);
+    battle
+        .allies
+        .get_mut_ch_by_pos(1)
+        .unwrap()
+        .hit_pool(&String::from("HP"), 5);
+    let mut used = Vec::<Name>::new();
+    for _ in 0..3 {
+        battle.current_npc_idx = Some(PlayerIndex::Baddy(0));
+        battle.play_npc_action();
+        if let Some(BattleEvent::ActionUsed {
+            action, targets, ..
+        }) = battle.events.back()
+        {
+            used.push(action.clone());
+            assert_eq!(targets.len(), 1);
+        }
+        battle.effects.clear();
+    }
+    assert_eq!(used, vec!["Attack", "Attack", "Bolt 2"]);
+    // Below half HP it goes for the weakest
+    let boss = battle.baddies.get_mut_ch_by_pos(0).unwrap();
+    boss.hit_pool(&String::from("HP"), 200);
+    boss.hit_pool(&String::from("MP"), 30);
+    battle.current_npc_idx = Some(PlayerIndex::Baddy(0));
+    battle.play_npc_action();
+    assert!(matches!(
+        battle.events.back(),
+        Some(BattleEvent::ActionUsed { action, targets, .. })
+            if action == "Attack" && targets == &vec![PlayerIndex::Ally(1)]
+    ));
+}
+
+#[test]
+fn scope_targets_test() {
+    use PlayerIndex::{Ally, Baddy};
+    let mut battle = get_test_battle(2, 3);
+    battle
+        .baddies
+        .get_mut_ch_by_pos(1)
+        .unwrap()
+        .hit_pool(&String::from("HP"), 9999);
+    let targets = |scope, revives| battle.get_scope_targets(&Ally(0), scope, revives);
+    assert!(targets(Scope::None, false).is_empty());
+    assert_eq!(targets(Scope::Me, false), vec![Ally(0)]);
+    assert_eq!(targets(Scope::You, false), vec![Ally(1)]);
+    assert_eq!(targets(Scope::MyAllies, false), vec![Ally(1)]);
+    assert_eq!(targets(Scope::Allies, false), vec![Ally(0), Ally(1)]);
+    assert_eq!(targets(Scope::Enemies, false), vec![Baddy(0), Baddy(2)]);
+    assert_eq!(
+        targets(Scope::Enemies, true),
+        vec![Baddy(0), Baddy(1), Baddy(2)]
+    );
+    assert_eq!(
+        targets(Scope::One, false),
+        vec![Baddy(0), Baddy(2), Ally(0), Ally(1)]
+    );
+    // Sides are relative to the actor
+    assert_eq!(
+        battle.get_scope_targets(&Baddy(2), Scope::MyAllies, false),
+        vec![Baddy(0)]
+    );
+    assert_eq!(
+        battle.get_scope_targets(&Baddy(2), Scope::Enemy, false),
+        vec![Ally(0), Ally(1)]
+    );
+}
+
+#[test]
+fn move_targets_test() {
+    use PlayerIndex::{Ally, Baddy};
+    let mut battle = get_test_battle(2, 2);
+    battle.force_turn(Ally(0));
+    battle.start_targeting(Targeting {
+        scope: Scope::One,
+        spread: true,
+        revives: false,
+    });
+    assert_eq!(battle.targets, vec![Baddy(0)]);
+    battle.make_selection(Key::Left);
+    assert_eq!(battle.targets, vec![Baddy(1)]);
+    battle.make_selection(Key::Up);
+    assert_eq!(battle.targets, vec![Baddy(0), Baddy(1)]);
+    battle.make_selection(Key::Down);
+    assert_eq!(battle.targets, vec![Baddy(0)]);
+    battle.make_selection(Key::Down);
+    assert_eq!(battle.targets, vec![Ally(0)]);
+    battle.make_selection(Key::Right);
+    assert_eq!(battle.targets, vec![Ally(1)]);
+    battle.make_selection(Key::Esc);
+    assert!(battle.targets.is_empty());
+    // Groups can't be narrowed down
+    battle.start_targeting(Targeting {
+        scope: Scope::Enemies,
+        spread: false,
+        revives: false,
+    });
+    battle.make_selection(Key::Down);
+    assert_eq!(battle.targets, vec![Baddy(0), Baddy(1)]);
+}
+
+#[test]
+fn revive_targeting_test() {
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let mut allies = Party::new(String::from("Allies"));
+    allies.add_clone(&IndexedOrLiteral::Index(10), &ch_enc); // Mog
+    allies.add_clone(&IndexedOrLiteral::Index(0), &ch_enc);
+    let (_, baddies) = get_test_parties(0, 1);
+    let mut battle = Battle::new(allies, baddies);
+    battle
+        .allies
+        .get_mut_ch_by_pos(1)
+        .unwrap()
+        .hit_pool(&String::from("HP"), 9999);
+    let phoenix_down = battle.item_enc.get(&3).unwrap();
+    assert!(battle.revives(phoenix_down.get_effect()));
+    let potion = battle.item_enc.get(&0).unwrap();
+    assert!(!battle.revives(potion.get_effect()));
+    assert_eq!(
+        battle.get_scope_targets(&PlayerIndex::Ally(0), Scope::You, false),
+        vec![]
+    );
+    assert_eq!(
+        battle.get_scope_targets(&PlayerIndex::Ally(0), Scope::You, true),
+        vec![PlayerIndex::Ally(1)]
+    );
+}
+
+fn play_effects(battle: &mut Battle) {
+    while !battle.effects.is_empty() || !battle.hits.is_empty() {
+        battle.handle_effect();
+        battle.handle_hit();
+        battle.handle_condition();
+    }
+}
+
+fn give_condition(battle: &mut Battle, pi: &PlayerIndex, name: &str) {
+    let id = battle.condition_enc.find_by_name(name).unwrap();
+    let con = battle.condition_enc.get(&id).unwrap();
+    get_mut_ch(&mut battle.allies, &mut battle.baddies, pi).take_condition(id, con);
+}
+
+#[test]
+fn counter_test() {
+    // Mog has the HP to live through a critical hit and counter
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let (allies, mut baddies) = get_test_parties(1, 0);
+    baddies.add_clone(&IndexedOrLiteral::Index(10), &ch_enc);
+    let mut battle = Battle::new(allies, baddies);
+    give_condition(&mut battle, &PlayerIndex::Baddy(0), "Counter");
+    // Two counters can't keep countering each other
+    give_condition(&mut battle, &PlayerIndex::Ally(0), "Counter");
+    battle.effects.push_back(TargetedEffect::new(
+        &PlayerIndex::Ally(0),
+        &PlayerIndex::Baddy(0),
+        &IndexedOrLiteral::Index(0), // Physical
+        &battle.effect_enc,
+    ));
+    battle.handle_effect();
+    assert_eq!(battle.effects.len(), 1);
+    play_effects(&mut battle);
+    let log = battle.get_event_log();
+    let counters: Vec<&BattleEvent> = log
+        .iter()
+        .filter(|e| matches!(e, BattleEvent::Countered { .. }))
+        .collect();
+    assert_eq!(
+        counters,
+        vec![&BattleEvent::Countered {
+            pi: PlayerIndex::Baddy(0),
+            name: String::from("Mog"),
+        }]
+    );
+    assert!(log.iter().any(|e| matches!(
+        e,
+        BattleEvent::Damage {
+            target: PlayerIndex::Ally(0),
+            ..
+        }
+    )));
+    // Magic isn't countered
+    let mut battle = get_test_battle(1, 1);
+    give_condition(&mut battle, &PlayerIndex::Baddy(0), "Counter");
+    battle.effects.push_back(TargetedEffect::new(
+        &PlayerIndex::Ally(0),
+        &PlayerIndex::Baddy(0),
+        &IndexedOrLiteral::Index(25506020),
+        &battle.effect_enc,
+    ));
+    battle.handle_effect();
+    assert!(battle.effects.is_empty());
+}
+
+#[test]
+fn reflect_test() {
+    let mut battle = get_test_battle(1, 1);
+    give_condition(&mut battle, &PlayerIndex::Baddy(0), "Reflect");
+    // Reflected spells don't bounce back again
+    give_condition(&mut battle, &PlayerIndex::Ally(0), "Reflect");
+    battle.effects.push_back(TargetedEffect::new(
+        &PlayerIndex::Ally(0),
+        &PlayerIndex::Baddy(0),
+        &IndexedOrLiteral::Index(25506020), // Magic
+        &battle.effect_enc,
+    ));
+    battle.handle_effect();
+    assert_eq!(
+        battle.events.back().unwrap().to_string(),
+        "Test Dummy T1 reflected it back!"
+    );
+    assert!(battle.effects.is_empty());
+    assert!(battle
+        .hits
+        .iter()
+        .all(|th| th.target_pi == PlayerIndex::Ally(0)));
+    assert!(!battle.hits.is_empty());
+}
+
+#[test]
+fn auto_potion_test() {
+    let item_enc = ItemEncyclopedia::new("data/items.json");
+    let charm = item_enc.clone_entry(&IndexedOrLiteral::Index(104)).unwrap();
+    let mut battle = get_test_battle(1, 1);
+    let hp = String::from("HP");
+    let ally = battle.allies.get_mut_ch_by_pos(0).unwrap();
+    ally.equip_to_slot(charm, String::from("Relic")).unwrap();
+    let max_hp = ally.get_pool_vals(hp.clone()).unwrap().1;
+    let poke = |battle: &mut Battle| {
+        battle.effects.push_back(TargetedEffect::new(
+            &PlayerIndex::Baddy(0),
+            &PlayerIndex::Ally(0),
+            &IndexedOrLiteral::Index(735730),
+            &battle.effect_enc,
+        ));
+        play_effects(battle);
+    };
+    let reacted = |battle: &Battle| {
+        battle
+            .get_event_log()
+            .iter()
+            .any(|e| matches!(e, BattleEvent::Reacted { effect, .. } if effect == "Potion"))
+    };
+    // Healthy enough to go without
+    poke(&mut battle);
+    assert!(!reacted(&battle));
+    // Brought low, the potion goes off once the poke lands
+    let ally = battle.allies.get_mut_ch_by_pos(0).unwrap();
+    let current = ally.get_pool_vals(hp.clone()).unwrap().0;
+    ally.hit_pool(&hp, current - max_hp / 4);
+    poke(&mut battle);
+    assert!(reacted(&battle));
+    let ally = battle.allies.get_ch_by_pos(0).unwrap();
+    assert!(ally.get_pool_vals(hp).unwrap().0 > max_hp / 4);
+}
+
+#[test]
+fn atb_tick_test() {
+    let mut battle = get_test_battle(1, 1);
+    battle.pop_event(); // Battle start!
+    battle.tick();
+    assert_eq!(battle.get_gauge(&PlayerIndex::Ally(0)), 0);
+    battle.mode = BattleMode::Active;
+    while battle.current_pc_idx.is_none() {
+        battle.tick();
+    }
+    assert!(battle.get_gauge(&PlayerIndex::Baddy(0)) > 0);
+    // Time stands still while messages are up
+    let clock = battle.get_gauge(&PlayerIndex::Baddy(0));
+    battle.tick();
+    assert_eq!(battle.get_gauge(&PlayerIndex::Baddy(0)), clock);
+    battle.pop_event();
+    // and in Wait mode while the PC is in a submenu
+    battle.mode = BattleMode::Wait;
+    battle.selections.push(0);
+    battle.tick();
+    assert!(battle.get_event().is_none());
+    battle.selections.pop();
+    // The just as fast baddy doesn't wait for the PC to pick
+    while battle.get_event().is_none() {
+        battle.tick();
+    }
+    assert!(matches!(
+        battle.get_event(),
+        Some(BattleEvent::TurnStarted {
+            pi: PlayerIndex::Baddy(0),
+            ..
+        })
+    ));
+    assert_eq!(battle.current_pc_idx, Some(PlayerIndex::Ally(0)));
+}
+
+#[test]
+fn atb_replay_test() {
+    let (allies, baddies) = get_test_parties(1, 1);
+    let mut battle = Battle::with_seed(allies, baddies, 3);
+    battle.mode = BattleMode::Active;
+    let mut ui = headless::HeadlessBattleUI::from_script(&"select Attack\n".repeat(10)).unwrap();
+    let result = battle.run(&mut ui);
+    assert_ne!(result.outcome, BattleOutcome::Aborted);
+    let replay = result.replay.unwrap();
+    assert!(replay.get_keys().contains(&replay::ReplayKey::Tick));
+    let keys = replay.get_keys().to_vec();
+    let (battle, mut ui) = replay.into_battle();
+    assert_eq!(battle.mode, BattleMode::Active);
+    let replayed = battle.run(&mut ui);
+    assert_eq!(replayed.replay.unwrap().get_keys(), &keys[..]);
+    assert_eq!(replayed.turns, result.turns);
+    assert_eq!(replayed.outcome, result.outcome);
+}
+
+#[test]
+fn rounds_battle_test() {
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let (mut allies, mut baddies) = get_test_parties(0, 0);
+    allies.add_clone(&IndexedOrLiteral::Index(515), &ch_enc);
+    baddies.add_clone(&IndexedOrLiteral::Index(10), &ch_enc); // Mog, who's faster
+    let mut battle = Battle::with_seed(allies, baddies, 5);
+    battle.set_turn_order(turnorder::TurnSystem::Rounds.new_order());
+    let mut ui = headless::HeadlessBattleUI::from_script("select Attack, select Attack").unwrap();
+    let result = battle.run(&mut ui);
+    let events = ui.get_events();
+    let position = |found: &dyn Fn(&BattleEvent) -> bool| events.iter().position(found).unwrap();
+    let chosen = position(
+        &|e| matches!(e, BattleEvent::CommandChosen { command, .. } if command == "Attack"),
+    );
+    let pc_attack = position(&|e| {
+        matches!(
+            e,
+            BattleEvent::ActionUsed {
+                actor: PlayerIndex::Ally(0),
+                ..
+            }
+        )
+    });
+    let mog_action = position(&|e| {
+        matches!(
+            e,
+            BattleEvent::ActionUsed {
+                actor: PlayerIndex::Baddy(0),
+                ..
+            }
+        )
+    });
+    // The PC picks first, but Mog acts first
+    assert!(chosen < mog_action);
+    assert!(mog_action < pc_attack);
+    // Replays come back with the same TurnOrder
+    let (battle, mut replay_ui) = result.replay.unwrap().into_battle();
+    assert_eq!(
+        battle.turn_order.get_system(),
+        Some(turnorder::TurnSystem::Rounds)
+    );
+    assert_eq!(battle.run(&mut replay_ui).turns, result.turns);
+}
+
+#[test]
+fn rounds_downed_pick_test() {
+    let mut battle = get_test_battle(1, 1);
+    battle.set_turn_order(turnorder::TurnSystem::Rounds.new_order());
+    while battle.events.pop_front().is_some() {}
+    // The PC picks Attack on the baddy
+    assert!(battle.start_ready_turn());
+    while battle.events.pop_front().is_some() {}
+    battle.handle_input(Key::Char('\n'));
+    battle.handle_input(Key::Char('\n'));
+    assert_eq!(battle.pending.len(), 1);
+    assert!(battle.current_pc_idx.is_none());
+    // ...but goes down before acting, so only the baddy gets a turn
+    let down_id = battle.condition_enc.find_down().unwrap();
+    let down = battle.condition_enc.get(&down_id).unwrap();
+    get_mut_ch(
+        &mut battle.allies,
+        &mut battle.baddies,
+        &PlayerIndex::Ally(0),
+    )
+    .take_condition(down_id, down);
+    assert!(battle.start_ready_turn());
+    assert!(battle.current_npc_idx.is_none());
+    get_mut_ch(
+        &mut battle.allies,
+        &mut battle.baddies,
+        &PlayerIndex::Ally(0),
+    )
+    .cure_conditions(&vec![String::from("Revive")], &battle.condition_enc);
+    assert!(!battle.is_player_down(&PlayerIndex::Ally(0)));
+    while battle.events.pop_front().is_some() {}
+    // Next round the PC picks again instead of replaying the old pick
+    battle.next_turn();
+    assert_eq!(battle.current_pc_idx, Some(PlayerIndex::Ally(0)));
+    assert_eq!(battle.selections, vec![0]);
+    assert!(battle.pending.is_empty());
+    while let Some(event) = battle.events.pop_front() {
+        assert!(!matches!(event, BattleEvent::ActionUsed { .. }));
+    }
+}
+
+#[test]
+fn predict_turns_test() {
+    let mut battle = get_test_battle(1, 1);
+    give_condition(&mut battle, &PlayerIndex::Baddy(0), "Slow");
+    battle.allies.clocks[0] = u16::MAX / 2;
+    let clocks = (battle.allies.clocks.clone(), battle.baddies.clocks.clone());
+    let turns = battle.predict_turns(6);
+    assert_eq!(turns.len(), 6);
+    assert_eq!(turns[0], PlayerIndex::Ally(0));
+    let slowed = turns
+        .iter()
+        .filter(|pi| **pi == PlayerIndex::Baddy(0))
+        .count();
+    assert!(slowed < 3);
+    // Nothing changes until turns are actually played
+    assert_eq!(
+        (battle.allies.clocks.clone(), battle.baddies.clocks.clone()),
+        clocks
+    );
+    assert_eq!(battle.predict_turns(6), turns);
+}
+
+#[test]
+fn affinity_test() {
+    let magic_damage = |shell: bool| {
+        let mut battle = get_test_battle(1, 1);
+        if shell {
+            give_condition(&mut battle, &PlayerIndex::Baddy(0), "Shell");
+        }
+        battle.effects.push_back(TargetedEffect::new(
+            &PlayerIndex::Ally(0),
+            &PlayerIndex::Baddy(0),
+            &IndexedOrLiteral::Index(25506020), // Magic
+            &battle.effect_enc,
+        ));
+        battle.handle_effect();
+        (battle.hits[0].amount, battle.events.back().cloned())
+    };
+    let (full, _) = magic_damage(false);
+    let (halved, event) = magic_damage(true);
+    assert_eq!(halved, full / 2);
+    assert_eq!(event.unwrap().to_string(), "It's not very effective...");
+    // The rat's weakness to Fire shows up in a Scan
+    let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+    let (allies, mut baddies) = get_test_parties(1, 0);
+    baddies.add_clone(&IndexedOrLiteral::Index(101), &ch_enc);
+    let mut battle = Battle::new(allies, baddies);
+    for effect in [800, 10] {
+        battle.effects.push_back(TargetedEffect::new(
+            &PlayerIndex::Ally(0),
+            &PlayerIndex::Baddy(0),
+            &IndexedOrLiteral::Index(effect), // Scan, then Candlelight
+            &battle.effect_enc,
+        ));
+    }
+    battle.handle_effect();
+    assert_eq!(
+        battle.events.back().unwrap().to_string(),
+        "Mouse-Sized Rat: weak to Fire."
+    );
+    assert!(battle.hits.is_empty());
+    battle.handle_effect();
+    assert_eq!(
+        battle.events.back(),
+        Some(&BattleEvent::AffinityHit {
+            target: PlayerIndex::Baddy(0),
+            name: String::from("Mouse-Sized Rat"),
+            affinity: crate::affinity::Affinity::Weak,
+        })
+    );
+}
+
+#[test]
+fn miss_and_critical_test() {
+    let (allies, mut baddies) = get_test_parties(1, 0);
+    baddies.add_character(Character::from_json(
+        r#"{"id": 1, "name": "Dodger", "base_stats": {"Evasion": 100},
+            "pools": {"HP": {"name": "HP", "current": 100, "maximum": 100}}}"#,
+    ));
+    let mut battle = Battle::new(allies, baddies);
+    let play = |battle: &mut Battle, effect: IndexedOrLiteral<Effect>| {
+        battle.effects.push_back(TargetedEffect::new(
+            &PlayerIndex::Ally(0),
+            &PlayerIndex::Baddy(0),
+            &effect,
+            &battle.effect_enc,
+        ));
+        battle.handle_effect();
+    };
+    // Physical attacks can't get past that Evasion, Conditions and all
+    play(&mut battle, IndexedOrLiteral::Index(0));
+    assert_eq!(battle.events.back().unwrap().to_string(), "Missed Dodger!");
+    assert!(battle.hits.is_empty() && battle.condition_changes.is_empty());
+    let sure_critical: Effect = serde_json::from_str(
+        r#"{"id": 1, "name": "Sure Critical",
+            "hits": [{"pool": "HP", "amount": {"Constant": 10}}],
+            "critical": {"chance": "100"}}"#,
+    )
+    .unwrap();
+    play(&mut battle, IndexedOrLiteral::Literal(sure_critical));
+    assert_eq!(
+        battle.events.back(),
+        Some(&BattleEvent::CriticalHit {
+            target: PlayerIndex::Baddy(0),
+            name: String::from("Dodger"),
+        })
+    );
+    assert_eq!(battle.hits[0].amount, 20);
+}
+
+#[test]
+fn turn_count_test() {
+    let mut battle = get_test_battle(2, 1);
+    while battle.pop_event().is_some() {}
+    battle.force_turn(PlayerIndex::Ally(0));
+    battle.selections.push(0);
+    battle.make_selection(Key::Char('\n')); // Attack
+    battle.make_selection(Key::Char('\n')); // the baddy
+    assert!(battle.get_current_pc().is_none());
+    // Each Character counts their own turns, allies included
+    assert_eq!(battle.get_ai_context(&PlayerIndex::Ally(0)).turn, 1);
+    assert_eq!(battle.get_ai_context(&PlayerIndex::Ally(1)).turn, 0);
+    assert_eq!(battle.get_ai_context(&PlayerIndex::Baddy(0)).turn, 0);
 }
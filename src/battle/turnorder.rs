@@ -0,0 +1,587 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::battle::PlayerIndex;
+use crate::character::Character;
+use crate::encyclopedia::StatBlockEncyclopedia;
+use crate::party::Party;
+
+/// Decides whose turn comes up when. Battle time passes in the same units
+/// as Condition durations, whatever the system.
+pub trait TurnOrder {
+    /// Lets dt of battle time pass
+    fn pass_time(
+        &mut self,
+        dt: u16,
+        allies: &mut Party,
+        baddies: &mut Party,
+        statblocks: &StatBlockEncyclopedia,
+    );
+    /// Takes whoever's turn has come up, if anyone's. While a PC is
+    /// picking an action (pcs_busy), other PCs have to wait.
+    fn take_ready(
+        &mut self,
+        allies: &mut Party,
+        baddies: &mut Party,
+        statblocks: &StatBlockEncyclopedia,
+        pcs_busy: bool,
+    ) -> Option<PlayerIndex>;
+    /// How close a Character is to their next turn, out of u16::MAX
+    fn get_gauge(&self, pi: &PlayerIndex, allies: &Party, baddies: &Party) -> u16;
+    /// The next n Characters to act as things stand, if the system can tell,
+    /// allowing for Haste, Slow and the like wearing off. Picking a command
+    /// to be played later isn't a turn of its own here.
+    fn predict(
+        &self,
+        _n: usize,
+        _allies: &Party,
+        _baddies: &Party,
+        _statblocks: &StatBlockEncyclopedia,
+    ) -> Vec<PlayerIndex> {
+        Vec::new()
+    }
+    /// Whether PCs pick their commands up front, to be played on their turn
+    fn defers_commands(&self) -> bool {
+        false
+    }
+    /// Whether the turn take_ready last handed out is for picking a
+    /// command, rather than playing one picked earlier
+    fn is_picking_turn(&self) -> bool {
+        false
+    }
+    /// Which built-in system this is, so Replays can set it up again
+    fn get_system(&self) -> Option<TurnSystem> {
+        None
+    }
+}
+
+/// The built-in TurnOrders
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TurnSystem {
+    #[default]
+    Clocks,
+    Ctb,
+    Rounds,
+}
+
+impl TurnSystem {
+    pub fn new_order(&self) -> Box<dyn TurnOrder> {
+        match self {
+            TurnSystem::Clocks => Box::new(Clocks),
+            TurnSystem::Ctb => Box::new(Ctb::default()),
+            TurnSystem::Rounds => Box::new(Rounds::default()),
+        }
+    }
+}
+
+// Battle time for a turn to come up at a Speed of 1
+const TURN_TIME: u32 = u16::MAX as u32;
+
+fn get_party<'a>(pi: &PlayerIndex, allies: &'a Party, baddies: &'a Party) -> (&'a Party, usize) {
+    match pi {
+        PlayerIndex::Ally(i) => (allies, *i),
+        PlayerIndex::Baddy(i) => (baddies, *i),
+    }
+}
+
+// Everyone who's up, allies first
+fn up_members<'a>(allies: &'a Party, baddies: &'a Party) -> Vec<(PlayerIndex, &'a Character)> {
+    let side = |party: &'a Party, ally: bool| {
+        (0..party.len())
+            .filter_map(|i| party.get_ch_by_pos(i).map(|c| (i, c)))
+            .filter(|(_, c)| !c.is_down())
+            .map(|(i, c)| {
+                let pi = if ally {
+                    PlayerIndex::Ally(i)
+                } else {
+                    PlayerIndex::Baddy(i)
+                };
+                (pi, c)
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut members = side(allies, true);
+    members.append(&mut side(baddies, false));
+    members
+}
+
+// Copies of everyone who's up, for predict to wear Conditions off of
+fn forecast_members(allies: &Party, baddies: &Party) -> Vec<(PlayerIndex, Character)> {
+    up_members(allies, baddies)
+        .into_iter()
+        .map(|(pi, c)| (pi, c.clone()))
+        .collect()
+}
+
+// Battle time between a Character's turns, or None if they're down
+fn turn_length(c: &Character, statblocks: &StatBlockEncyclopedia) -> Option<u32> {
+    let speed = c.dclock(1, statblocks) as u32;
+    (speed > 0).then(|| TURN_TIME / speed)
+}
+
+/// FFVI's Active Time Battle: everyone's clock fills with their Speed,
+/// and a turn comes up when it's full
+pub struct Clocks;
+
+impl TurnOrder for Clocks {
+    fn pass_time(
+        &mut self,
+        dt: u16,
+        allies: &mut Party,
+        baddies: &mut Party,
+        statblocks: &StatBlockEncyclopedia,
+    ) {
+        allies.fill_clocks(dt, statblocks);
+        baddies.fill_clocks(dt, statblocks);
+    }
+    fn take_ready(
+        &mut self,
+        allies: &mut Party,
+        baddies: &mut Party,
+        _statblocks: &StatBlockEncyclopedia,
+        pcs_busy: bool,
+    ) -> Option<PlayerIndex> {
+        if !pcs_busy {
+            if let Some(i) = allies.get_ready_ch_pos() {
+                return Some(PlayerIndex::Ally(i));
+            }
+        }
+        baddies.get_ready_ch_pos().map(PlayerIndex::Baddy)
+    }
+    fn get_gauge(&self, pi: &PlayerIndex, allies: &Party, baddies: &Party) -> u16 {
+        let (party, i) = get_party(pi, allies, baddies);
+        party.clocks.get(i).copied().unwrap_or(0)
+    }
+    fn predict(
+        &self,
+        n: usize,
+        allies: &Party,
+        baddies: &Party,
+        statblocks: &StatBlockEncyclopedia,
+    ) -> Vec<PlayerIndex> {
+        let mut members: Vec<(PlayerIndex, Character, u16)> = forecast_members(allies, baddies)
+            .into_iter()
+            .map(|(pi, c)| {
+                let clock = self.get_gauge(&pi, allies, baddies);
+                (pi, c, clock)
+            })
+            .collect();
+        let mut turns = Vec::<PlayerIndex>::new();
+        while turns.len() < n {
+            // Full clocks go first, allies before baddies like take_ready
+            if let Some((pi, _, clock)) = members.iter_mut().find(|m| m.2 == u16::MAX) {
+                *clock = 0;
+                turns.push(pi.clone());
+                continue;
+            }
+            // Skip ahead to the next full clock or change in Speed
+            let fill = members.iter().filter_map(|(_, c, clock)| {
+                let speed = c.dclock(1, statblocks);
+                (speed > 0).then(|| (u16::MAX - clock).div_ceil(speed))
+            });
+            let wear = members.iter().filter_map(|(_, c, _)| c.next_wear_off());
+            let Some(dt) = fill.chain(wear).min() else {
+                break;
+            };
+            for (_, c, clock) in members.iter_mut() {
+                *clock = clock.saturating_add(c.dclock(dt, statblocks));
+                c.wear_off(dt);
+            }
+        }
+        turns
+    }
+    fn get_system(&self) -> Option<TurnSystem> {
+        Some(TurnSystem::Clocks)
+    }
+}
+
+/// FFX's Conditional Turn Battle: each turn is scheduled when the last one
+/// starts, by the Speed at the time, so the queue ahead is known exactly
+/// until Haste, Slow or K.O. change it.
+#[derive(Default)]
+pub struct Ctb {
+    // Battle time until each Character's next turn, and how long it was
+    allies: Vec<(u32, u32)>,
+    baddies: Vec<(u32, u32)>,
+}
+
+impl Ctb {
+    // Schedules the first turns of anyone new
+    fn sync(&mut self, allies: &Party, baddies: &Party, statblocks: &StatBlockEncyclopedia) {
+        for (party, countdowns) in [(allies, &mut self.allies), (baddies, &mut self.baddies)] {
+            for i in countdowns.len()..party.len() {
+                let length = party
+                    .get_ch_by_pos(i)
+                    .and_then(|c| turn_length(c, statblocks))
+                    .unwrap_or(TURN_TIME);
+                countdowns.push((length, length));
+            }
+        }
+    }
+    fn get_countdown(&self, pi: &PlayerIndex) -> Option<(u32, u32)> {
+        match pi {
+            PlayerIndex::Ally(i) => self.allies.get(*i).copied(),
+            PlayerIndex::Baddy(i) => self.baddies.get(*i).copied(),
+        }
+    }
+    fn get_mut_countdown(&mut self, pi: &PlayerIndex) -> Option<&mut (u32, u32)> {
+        match pi {
+            PlayerIndex::Ally(i) => self.allies.get_mut(*i),
+            PlayerIndex::Baddy(i) => self.baddies.get_mut(*i),
+        }
+    }
+}
+
+impl TurnOrder for Ctb {
+    fn pass_time(
+        &mut self,
+        dt: u16,
+        allies: &mut Party,
+        baddies: &mut Party,
+        statblocks: &StatBlockEncyclopedia,
+    ) {
+        self.sync(allies, baddies, statblocks);
+        for (pi, _) in up_members(allies, baddies) {
+            if let Some((left, _)) = self.get_mut_countdown(&pi) {
+                *left = left.saturating_sub(dt as u32);
+            }
+        }
+    }
+    fn take_ready(
+        &mut self,
+        allies: &mut Party,
+        baddies: &mut Party,
+        statblocks: &StatBlockEncyclopedia,
+        pcs_busy: bool,
+    ) -> Option<PlayerIndex> {
+        self.sync(allies, baddies, statblocks);
+        let (pi, c) = up_members(allies, baddies)
+            .into_iter()
+            .filter(|(pi, _)| !(pcs_busy && matches!(pi, PlayerIndex::Ally(_))))
+            .find(|(pi, _)| self.get_countdown(pi).is_some_and(|(left, _)| left == 0))?;
+        let length = turn_length(c, statblocks).unwrap_or(TURN_TIME);
+        *self.get_mut_countdown(&pi)? = (length, length);
+        Some(pi)
+    }
+    fn get_gauge(&self, pi: &PlayerIndex, _allies: &Party, _baddies: &Party) -> u16 {
+        match self.get_countdown(pi) {
+            Some((left, length)) if length > 0 => {
+                ((length - left.min(length)) as u64 * u16::MAX as u64 / length as u64) as u16
+            }
+            _ => 0,
+        }
+    }
+    fn predict(
+        &self,
+        n: usize,
+        allies: &Party,
+        baddies: &Party,
+        statblocks: &StatBlockEncyclopedia,
+    ) -> Vec<PlayerIndex> {
+        let mut queue: Vec<(PlayerIndex, Character, u32)> = forecast_members(allies, baddies)
+            .into_iter()
+            .filter_map(|(pi, c)| {
+                let length = turn_length(&c, statblocks)?;
+                let left = self.get_countdown(&pi).map_or(length, |(left, _)| left);
+                Some((pi, c, left))
+            })
+            .collect();
+        let mut turns = Vec::<PlayerIndex>::new();
+        while turns.len() < n && !queue.is_empty() {
+            // Ties go to whoever comes first, like take_ready
+            let next = (0..queue.len()).min_by_key(|&i| queue[i].2).unwrap();
+            let elapsed = queue[next].2;
+            for (_, c, left) in queue.iter_mut() {
+                *left -= elapsed;
+                c.wear_off(u16::try_from(elapsed).unwrap_or(u16::MAX));
+            }
+            // The next turn is scheduled by the Speed at the time
+            let (pi, c, left) = &mut queue[next];
+            *left = turn_length(c, statblocks).unwrap_or(TURN_TIME);
+            turns.push(pi.clone());
+        }
+        turns
+    }
+    fn get_system(&self) -> Option<TurnSystem> {
+        Some(TurnSystem::Ctb)
+    }
+}
+
+/// Classic round-based initiative: every PC picks a command, then everyone
+/// acts in order of Speed. Rounds are a Speed 30 turn apart in battle time.
+pub struct Rounds {
+    // PCs yet to pick a command this round
+    picking: VecDeque<PlayerIndex>,
+    // Everyone yet to act this round, fastest first
+    acting: VecDeque<PlayerIndex>,
+    // Battle time since the last round ended
+    elapsed: u32,
+    // Whether the last turn taken came from picking
+    picking_turn: bool,
+}
+
+const ROUND_TIME: u32 = TURN_TIME / 30;
+
+impl Default for Rounds {
+    fn default() -> Rounds {
+        Rounds {
+            picking: VecDeque::new(),
+            acting: VecDeque::new(),
+            // The first round starts right away
+            elapsed: ROUND_TIME,
+            picking_turn: false,
+        }
+    }
+}
+
+impl Rounds {
+    fn start_round(&mut self, allies: &Party, baddies: &Party, statblocks: &StatBlockEncyclopedia) {
+        let mut members = up_members(allies, baddies);
+        self.picking = members
+            .iter()
+            .filter(|(pi, _)| matches!(pi, PlayerIndex::Ally(_)))
+            .map(|(pi, _)| pi.clone())
+            .collect();
+        // Stable, so ties keep allies first
+        members.sort_by_key(|(_, c)| std::cmp::Reverse(c.dclock(1, statblocks)));
+        self.acting = members.into_iter().map(|(pi, _)| pi).collect();
+        self.elapsed = 0;
+    }
+    fn is_between_rounds(&self) -> bool {
+        self.picking.is_empty() && self.acting.is_empty()
+    }
+}
+
+impl TurnOrder for Rounds {
+    fn pass_time(
+        &mut self,
+        dt: u16,
+        _allies: &mut Party,
+        _baddies: &mut Party,
+        _statblocks: &StatBlockEncyclopedia,
+    ) {
+        if self.is_between_rounds() {
+            self.elapsed = self.elapsed.saturating_add(dt as u32);
+        }
+    }
+    fn take_ready(
+        &mut self,
+        allies: &mut Party,
+        baddies: &mut Party,
+        statblocks: &StatBlockEncyclopedia,
+        pcs_busy: bool,
+    ) -> Option<PlayerIndex> {
+        // Nothing else happens while a PC picks
+        if pcs_busy {
+            return None;
+        }
+        if self.is_between_rounds() {
+            if self.elapsed < ROUND_TIME {
+                return None;
+            }
+            self.start_round(allies, baddies, statblocks);
+        }
+        let is_up = |pi: &PlayerIndex| {
+            let (party, i) = get_party(pi, allies, baddies);
+            party.get_ch_by_pos(i).is_some_and(|c| !c.is_down())
+        };
+        while let Some(pi) = self.picking.pop_front() {
+            if is_up(&pi) {
+                self.picking_turn = true;
+                return Some(pi);
+            }
+        }
+        self.picking_turn = false;
+        while let Some(pi) = self.acting.pop_front() {
+            if is_up(&pi) {
+                return Some(pi);
+            }
+        }
+        None
+    }
+    fn get_gauge(&self, pi: &PlayerIndex, _allies: &Party, _baddies: &Party) -> u16 {
+        if self.picking.contains(pi) || self.acting.contains(pi) {
+            return u16::MAX;
+        }
+        (self.elapsed.min(ROUND_TIME) as u64 * u16::MAX as u64 / ROUND_TIME as u64) as u16
+    }
+    fn predict(
+        &self,
+        n: usize,
+        allies: &Party,
+        baddies: &Party,
+        statblocks: &StatBlockEncyclopedia,
+    ) -> Vec<PlayerIndex> {
+        let mut members = forecast_members(allies, baddies);
+        // The rest of this round, then whole rounds as start_round would order them
+        let mut turns: Vec<PlayerIndex> = self
+            .acting
+            .iter()
+            .filter(|pi| members.iter().any(|(up, _)| up == *pi))
+            .take(n)
+            .cloned()
+            .collect();
+        let mut wait = ROUND_TIME - self.elapsed.min(ROUND_TIME);
+        while turns.len() < n && !members.is_empty() {
+            for (_, c) in members.iter_mut() {
+                c.wear_off(wait as u16);
+            }
+            wait = ROUND_TIME;
+            let mut order: Vec<usize> = (0..members.len()).collect();
+            order.sort_by_key(|&i| std::cmp::Reverse(members[i].1.dclock(1, statblocks)));
+            turns.extend(
+                order
+                    .into_iter()
+                    .take(n - turns.len())
+                    .map(|i| members[i].0.clone()),
+            );
+        }
+        turns
+    }
+    fn defers_commands(&self) -> bool {
+        true
+    }
+    fn is_picking_turn(&self) -> bool {
+        self.picking_turn
+    }
+    fn get_system(&self) -> Option<TurnSystem> {
+        Some(TurnSystem::Rounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::IndexedOrLiteral;
+    use crate::encyclopedia::{CharacterEncyclopedia, ConditionEncyclopedia};
+
+    // Test Dummy T1 has Speed 30, Mog has more
+    fn parties(hasted: bool) -> (Party, Party, StatBlockEncyclopedia) {
+        let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+        let mut allies = Party::new(String::from("Allies"));
+        let mut baddies = Party::new(String::from("Baddies"));
+        allies.add_clone(&IndexedOrLiteral::Index(0), &ch_enc);
+        baddies.add_clone(&IndexedOrLiteral::Index(10), &ch_enc);
+        if hasted {
+            let conditions = ConditionEncyclopedia::new("data/conditions.json");
+            let haste_id = conditions.find_by_name("Haste").unwrap();
+            let haste = conditions.get(&haste_id).unwrap();
+            allies
+                .get_mut_ch_by_pos(0)
+                .unwrap()
+                .take_condition(haste_id, haste);
+        }
+        (
+            allies,
+            baddies,
+            StatBlockEncyclopedia::new("data/stats.json"),
+        )
+    }
+
+    fn play(order: &mut dyn TurnOrder, turns: usize, hasted: bool) -> Vec<PlayerIndex> {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let (mut allies, mut baddies, statblocks) = parties(hasted);
+        let mut played = Vec::<PlayerIndex>::new();
+        while played.len() < turns {
+            match order.take_ready(&mut allies, &mut baddies, &statblocks, false) {
+                Some(pi) => played.push(pi),
+                None => {
+                    order.pass_time(1, &mut allies, &mut baddies, &statblocks);
+                    allies.experience_conditions(1, &conditions);
+                    baddies.experience_conditions(1, &conditions);
+                }
+            }
+        }
+        played
+    }
+
+    fn count_allies(turns: &[PlayerIndex]) -> usize {
+        turns
+            .iter()
+            .filter(|pi| matches!(pi, PlayerIndex::Ally(_)))
+            .count()
+    }
+
+    #[test]
+    fn ctb_predict_test() {
+        let (allies, baddies, statblocks) = parties(false);
+        let ctb = Ctb::default();
+        let predicted = ctb.predict(12, &allies, &baddies, &statblocks);
+        assert_eq!(predicted.len(), 12);
+        assert_eq!(play(&mut Ctb::default(), 12, false), predicted);
+        // The faster Mog gets more turns
+        let mogs = predicted
+            .iter()
+            .filter(|pi| matches!(pi, PlayerIndex::Baddy(_)))
+            .count();
+        assert_eq!(mogs, 7);
+    }
+    #[test]
+    fn rounds_test() {
+        let mut rounds = Rounds::default();
+        // The PC picks, then the faster Mog goes first
+        assert_eq!(
+            play(&mut rounds, 3, false),
+            vec![
+                PlayerIndex::Ally(0),
+                PlayerIndex::Baddy(0),
+                PlayerIndex::Ally(0)
+            ]
+        );
+        assert!(rounds.is_between_rounds());
+        assert!(
+            rounds.get_gauge(
+                &PlayerIndex::Ally(0),
+                &Party::new(String::new()),
+                &Party::new(String::new())
+            ) < u16::MAX
+        );
+        assert!(rounds.defers_commands());
+    }
+    #[test]
+    fn clocks_test() {
+        let played = play(&mut Clocks, 4, false);
+        assert_eq!(played.len(), 4);
+        assert!(played.contains(&PlayerIndex::Ally(0)));
+        assert!(!Clocks.defers_commands());
+    }
+    #[test]
+    fn haste_predict_test() {
+        for system in [TurnSystem::Clocks, TurnSystem::Ctb] {
+            let (allies, baddies, statblocks) = parties(true);
+            let predicted = system
+                .new_order()
+                .predict(20, &allies, &baddies, &statblocks);
+            // Haste wears off partway through
+            assert_eq!(play(&mut *system.new_order(), 20, true), predicted);
+            let (allies, baddies, _) = parties(false);
+            let unhasted = system
+                .new_order()
+                .predict(20, &allies, &baddies, &statblocks);
+            assert!(count_allies(&predicted) > count_allies(&unhasted));
+        }
+    }
+    #[test]
+    fn rounds_predict_test() {
+        let (mut allies, mut baddies, statblocks) = parties(false);
+        let mut rounds = Rounds::default();
+        let fresh = rounds.predict(4, &allies, &baddies, &statblocks);
+        assert_eq!(
+            fresh,
+            vec![
+                PlayerIndex::Baddy(0),
+                PlayerIndex::Ally(0),
+                PlayerIndex::Baddy(0),
+                PlayerIndex::Ally(0)
+            ]
+        );
+        // Once the PC has picked, only the rest of the round is left of it
+        rounds.take_ready(&mut allies, &mut baddies, &statblocks, false);
+        rounds.take_ready(&mut allies, &mut baddies, &statblocks, false);
+        assert_eq!(
+            rounds.predict(3, &allies, &baddies, &statblocks),
+            fresh[1..].to_vec()
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
 use termion::clear::All as ClearAll;
 use termion::color;
 use termion::cursor::Goto;
@@ -6,7 +8,7 @@ use termion::event::Key;
 use termion::style;
 
 use crate::battle::battleui::BattleUI;
-use crate::battle::{Battle, PlayerIndex};
+use crate::battle::{Battle, BattleMode, PlayerIndex};
 use crate::party::Party;
 
 const OUTER_ROW: &str = r" =================================== ";
@@ -17,7 +19,13 @@ const TARGET_OUTER_ROW: &str = r" |\/\/\/\/\/\/\/\/|\/\/\/\/\/\/\/\/| ";
 const TARGET_INNER_ROW: &str = r" >                                 < ";
 const BOX_HEIGHT: u16 = 8;
 const BOX_WIDTH: u16 = 37;
+const GAUGE_WIDTH: usize = 20;
+// How many upcoming turns the sidebar shows
+const PREVIEW_LENGTH: usize = 8;
+// How often poll_key checks for input
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
 
+/// For the ATB BattleModes, stdin should not block, e.g. termion::async_stdin
 pub struct BattleCLI<R: Iterator<Item = Result<Key, std::io::Error>>, W: Write> {
     pub stdin: R,
     pub stdout: W,
@@ -36,13 +44,30 @@ impl<R: Iterator<Item = Result<Key, std::io::Error>>, W: Write> BattleUI for Bat
         self.draw_boxes(battle.baddies.len(), battle.allies.len(), battle);
         self.write_baddies_info(&battle.baddies);
         self.write_allies_info(&battle.allies);
-        self.write_text(battle.get_text());
+        if battle.mode != BattleMode::Turns {
+            self.write_gauges(battle);
+        }
+        self.write_turn_preview(battle);
+        self.write_text(battle.get_text().as_ref());
         self.write_menus(battle);
     }
     fn get_key(&mut self) -> Key {
         self.stdout.flush().unwrap();
         self.stdin.next().unwrap().unwrap()
     }
+    fn poll_key(&mut self, timeout: Duration) -> Option<Key> {
+        self.stdout.flush().unwrap();
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(key) = self.stdin.next() {
+                return Some(key.unwrap());
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
 }
 
 impl<R: Iterator<Item = Result<Key, std::io::Error>>, W: Write> BattleCLI<R, W> {
@@ -186,6 +211,57 @@ impl<R: Iterator<Item = Result<Key, std::io::Error>>, W: Write> BattleCLI<R, W>
         write!(self.stdout, "\n\n\n").unwrap();
         self._draw_boxes(num_allies, false, battle); // fixme
     }
+    fn write_gauges(&mut self, battle: &Battle) {
+        for (n, baddies, y) in [
+            (battle.baddies.len(), true, 7),
+            (battle.allies.len(), false, BOX_HEIGHT + 4 + 7),
+        ] {
+            for i in 0..n {
+                let pi = if baddies {
+                    PlayerIndex::Baddy(i)
+                } else {
+                    PlayerIndex::Ally(i)
+                };
+                let full = battle.get_gauge(&pi) as usize * GAUGE_WIDTH / u16::MAX as usize;
+                let i: u16 = i.try_into().unwrap();
+                write!(
+                    self.stdout,
+                    "{} ATB [{}{}]",
+                    Goto(i * BOX_WIDTH + 3, y),
+                    "#".repeat(full),
+                    ".".repeat(GAUGE_WIDTH - full)
+                )
+                .unwrap();
+            }
+        }
+    }
+    fn write_turn_preview(&mut self, battle: &Battle) {
+        let turns = battle.predict_turns(PREVIEW_LENGTH);
+        if turns.is_empty() {
+            return;
+        }
+        let widest = battle.baddies.len().max(battle.allies.len());
+        let x: u16 = (widest * BOX_WIDTH as usize + 3).try_into().unwrap();
+        write!(self.stdout, "{} Next up:", Goto(x, 2)).unwrap();
+        for (i, pi) in turns.iter().enumerate() {
+            let (party, pos, fg) = match pi {
+                PlayerIndex::Ally(pos) => (&battle.allies, *pos, color::AnsiValue::grayscale(23)),
+                PlayerIndex::Baddy(pos) => (&battle.baddies, *pos, color::AnsiValue(203)),
+            };
+            let name = party.get_ch_by_pos(pos).unwrap().whoami().1;
+            let i: u16 = i.try_into().unwrap();
+            write!(
+                self.stdout,
+                "{} {}. {}{}{}",
+                Goto(x, 4 + i),
+                i + 1,
+                color::Fg(fg),
+                name,
+                color::Fg(color::Reset)
+            )
+            .unwrap();
+        }
+    }
     fn write_baddies_info(&mut self, p: &Party) {
         for i in 0..p.len() {
             let c = p.get_ch_by_pos(i).unwrap();
@@ -204,7 +280,6 @@ impl<R: Iterator<Item = Result<Key, std::io::Error>>, W: Write> BattleCLI<R, W>
                 )
                 .unwrap();
             }
-            //write!(self.stdout, "{} {}", Goto(i*BOX_WIDTH + 3, 7), p.clocks.get(i as usize).unwrap()).unwrap();
         }
     }
     fn write_allies_info(&mut self, p: &Party) {
@@ -232,7 +307,6 @@ impl<R: Iterator<Item = Result<Key, std::io::Error>>, W: Write> BattleCLI<R, W>
                 )
                 .unwrap();
             }
-            //write!(self.stdout, "{} {}", Goto(i*BOX_WIDTH + 3, BOX_HEIGHT+4+7), p.clocks.get(i as usize).unwrap()).unwrap();
         }
     }
 }
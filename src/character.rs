@@ -6,14 +6,21 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 
 use crate::action::{Action, ActionMenu, CharacterAction, Costs};
+use crate::affinity::{Affinities, Affinity};
+use crate::ai::AiScript;
 use crate::common::*;
-use crate::condition::{Condition, TargetConditions};
+use crate::condition::{Condition, ConditionTriggers, TargetCondition, TargetConditions};
+use crate::effect::Traits;
 use crate::encyclopedia::ActionEncyclopedia;
 use crate::encyclopedia::ConditionEncyclopedia;
 use crate::encyclopedia::EffectEncyclopedia;
 use crate::encyclopedia::StatBlockEncyclopedia;
 use crate::formula::eval_stat;
-use crate::stats::{BaseStats, DerivedStat, Stat};
+use crate::inventory::Gold;
+use crate::item::{self, DropTable, EquipError, Item};
+use crate::reaction::Reaction;
+use crate::stats;
+use crate::stats::{BaseStats, Buff, Buffs, DerivedStat, Experience, Growth, Stat, StatMod};
 
 type CharacterStats = Id; // todo, allow literals in JSON with enum
 type Items = Vec<Id>; // todo, allow literals in JSON with CharacterItem
@@ -44,15 +51,42 @@ pub struct Character {
     #[serde(default = "ActionMenu::new")]
     actions: ActionMenu,
     #[serde(default)]
+    class: Option<Name>,
+    #[serde(default)]
     items: Items,
-    // equips: item::EquipmentSet,
+    #[serde(default = "item::generate_equipment_set")]
+    equips: item::EquipmentSet,
     #[serde(default = "Character::default_pools")]
     pools: Pools,
     #[serde(default)]
     pub conditions: TargetConditions,
+    #[serde(default)]
+    pub buffs: Buffs,
+    #[serde(default)]
+    experience: Experience,
+    // Experience awarded for defeating the Character
+    #[serde(default)]
+    pub experience_reward: Experience,
+    // Overrides the StatBlock's growth
+    #[serde(default)]
+    growth: Option<Growth>,
+    // Loot for defeating the Character
+    #[serde(default)]
+    pub drops: DropTable,
+    #[serde(default)]
+    pub gold: Gold,
+    #[serde(default)]
+    steals: DropTable,
+    // How the Character fights when it isn't played, instead of at random
+    #[serde(default)]
+    ai: Option<AiScript>,
+    // How the Character takes Effects with certain traits, e.g. {"Fire": "Weak"}
+    #[serde(default)]
+    affinities: Affinities,
 }
 
 impl Character {
+    const RANDOM_ACTION_TRIES: usize = 16;
     fn default_base_stats() -> BaseStats {
         let mut bs = BaseStats::new();
         bs.insert(String::from("Offense"), 10);
@@ -92,10 +126,20 @@ impl Character {
             base_stats: Character::default_base_stats(),
             stats: Character::default_stats(),
             actions: ActionMenu::new(),
+            class: None,
             items: Items::new(),
-            //equips: item::generate_equipment_set(),
+            equips: item::generate_equipment_set(),
             pools: Character::default_pools(),
             conditions: TargetConditions::new(),
+            buffs: Buffs::new(),
+            experience: 0,
+            experience_reward: 0,
+            growth: None,
+            drops: DropTable::new(),
+            gold: 0,
+            steals: DropTable::new(),
+            ai: None,
+            affinities: Affinities::new(),
         }
     }
     pub fn matches(&self, id: Id) -> bool {
@@ -133,12 +177,78 @@ impl Character {
         default: Stat,
         statblocks: &StatBlockEncyclopedia,
     ) -> Stat {
+        // Stats without a formula in the StatBlock fall back to the base stat
         let base_stat = Name::from(&name);
         match self.get_stat(name, statblocks) {
             Some(formula) => eval_stat(base_stat, formula, self),
-            None => default,
+            None => self.get_base_stat(base_stat).copied().unwrap_or(default),
+        }
+    }
+    pub fn get_level(&self) -> Stat {
+        self.get_base_stat(String::from("Level"))
+            .copied()
+            .unwrap_or(1)
+    }
+    pub fn get_experience(&self) -> Experience {
+        self.experience
+    }
+    pub fn get_escape_formula<'s>(
+        &self,
+        statblocks: &'s StatBlockEncyclopedia,
+    ) -> Option<&'s HitFormula> {
+        statblocks.get(&self.stats).and_then(|sb| sb.get_escape())
+    }
+    pub fn get_ai(&self) -> Option<&AiScript> {
+        self.ai.as_ref()
+    }
+    pub fn get_growth<'s>(&'s self, statblocks: &'s StatBlockEncyclopedia) -> Option<&'s Growth> {
+        match &self.growth {
+            Some(growth) => Some(growth),
+            None => statblocks.get(&self.stats).and_then(|sb| sb.get_growth()),
+        }
+    }
+    /// Returns the number of levels gained
+    pub fn gain_experience(&mut self, xp: Experience, statblocks: &StatBlockEncyclopedia) -> Stat {
+        self.experience = self.experience.saturating_add(xp);
+        let growth = match self.get_growth(statblocks) {
+            Some(growth) => growth.clone(),
+            None => return 0,
+        };
+        let mut levels = 0;
+        while growth
+            .next_level_at(self.get_level())
+            .is_some_and(|next| self.experience >= next)
+        {
+            self.level_up(&growth);
+            levels += 1;
+        }
+        levels
+    }
+    fn level_up(&mut self, growth: &Growth) {
+        let level = self.get_level() + 1;
+        self.base_stats.insert(String::from("Level"), level);
+        for (name, gain) in &growth.stats {
+            *self.base_stats.entry(name.clone()).or_insert(0) += gain;
+        }
+        for (name, gain) in &growth.pools {
+            if let Some(pool) = self.pools.get_mut(name) {
+                pool.maximum += gain;
+                pool.current += gain;
+            }
         }
     }
+    /// Rolls the steal table, which is emptied by a successful steal
+    pub fn steal_from<R: Rng>(&mut self, rng: &mut R) -> Option<(Id, u16)> {
+        let stolen = self
+            .steals
+            .iter()
+            .find(|d| d.roll(rng))
+            .map(|d| (d.item, d.quantity));
+        if stolen.is_some() {
+            self.steals.clear();
+        }
+        stolen
+    }
     pub fn get_pool_vals(&self, name: String) -> Option<(i32, i32)> {
         self.pools
             .get(&name)
@@ -147,23 +257,55 @@ impl Character {
     pub fn get_pools(&self) -> &Pools {
         &self.pools
     }
-    /*
-    pub fn equip_to_slot(&mut self, item: item::Item, slot: String) -> Option<item::Item> {
-        let prev_equip = self.equips.remove(&slot).unwrap();
-        self.equips.insert(slot, Some(item));
-        prev_equip
+    /// Hands over the Items the Character starts with, e.g. to their Party
+    pub fn take_items(&mut self) -> Vec<Id> {
+        std::mem::take(&mut self.items)
+    }
+    pub fn get_class(&self) -> Option<&Name> {
+        self.class.as_ref()
+    }
+    pub fn get_equipped(&self, slot: &Name) -> Option<&Item> {
+        self.equips.get(slot).and_then(|e| e.as_ref())
+    }
+    pub fn can_equip(&self, item: &Item, slot: &Name) -> Result<(), EquipError> {
+        if !self.equips.contains_key(slot) {
+            return Err(EquipError::NoSuchSlot(slot.clone()));
+        }
+        if !item.fits_slot(slot) {
+            return Err(EquipError::WrongSlot {
+                item: item.copy_name(),
+                slot: slot.clone(),
+            });
+        }
+        if !item.allows_class(self.get_class()) {
+            return Err(EquipError::WrongClass {
+                item: item.copy_name(),
+            });
+        }
+        Ok(())
+    }
+    /// Equips the Item, handing back whatever was in the slot before.
+    /// On error the Item is handed back instead.
+    pub fn equip_to_slot(
+        &mut self,
+        item: Item,
+        slot: Name,
+    ) -> Result<Option<Item>, (EquipError, Box<Item>)> {
+        if let Err(e) = self.can_equip(&item, &slot) {
+            return Err((e, Box::new(item)));
+        }
+        Ok(self.equips.insert(slot, Some(item)).flatten())
+    }
+    pub fn unequip_from_slot(&mut self, slot: Name) -> Result<Option<Item>, EquipError> {
+        match self.equips.get_mut(&slot) {
+            Some(equip) => Ok(equip.take()),
+            None => Err(EquipError::NoSuchSlot(slot)),
+        }
     }
-    pub fn unequip_from_slot(&mut self, slot: String) -> Option<item::Item> {
-        let prev_equip = self.equips.remove(&slot).unwrap();
-        self.equips.insert(slot, None);
-        prev_equip
-    }*/
     pub fn get_item_attr(&self, slot: Name, attr: Name) -> Option<Stat> {
-        let _ = attr;
-        match slot.as_str() {
-            "Weapon" => Some(10),
-            "Armor" => Some(10),
-            _ => None,
+        match self.equips.get(&slot)? {
+            Some(item) => item.get_attr(&attr),
+            None => Some(0), // Nothing equipped
         }
     }
     pub fn get_action_options(
@@ -205,96 +347,144 @@ impl Character {
                 menu = m;
                 continue;
             }
+            if let CharacterAction::UseItem | CharacterAction::Run = ca {
+                return None;
+            }
+        }
+        None
+    }
+    pub fn is_run_selection(&self, selections: &[usize]) -> bool {
+        let mut menu: &ActionMenu = &self.actions; // ROOT ActionMenu
+        for s in selections {
+            match menu.get_option(*s) {
+                Some(CharacterAction::Menu(m)) => menu = m,
+                Some(CharacterAction::Run) => return true,
+                _ => return false,
+            }
+        }
+        false
+    }
+    /// Where in the selections the Item menu was opened, if it was
+    pub fn find_item_selection(&self, selections: &[usize]) -> Option<usize> {
+        let mut menu: &ActionMenu = &self.actions; // ROOT ActionMenu
+        for (depth, s) in selections.iter().enumerate() {
+            match menu.get_option(*s) {
+                Some(CharacterAction::Menu(m)) => menu = m,
+                Some(CharacterAction::UseItem) => return Some(depth),
+                _ => return None,
+            }
         }
         None
     }
-    pub fn get_random_action<'a>(
+    pub fn get_random_action<'a, R: Rng>(
         &'a self,
         action_enc: &'a ActionEncyclopedia,
+        rng: &mut R,
     ) -> Option<&'a Action> {
-        // TODO: Check for unusable actions and reroll in case
-        let mut menu: &ActionMenu = &self.actions; // ROOT ActionMenu
-        let mut rng = rand::thread_rng();
-        loop {
-            let s = rng.gen_range(0..menu.len());
-            let ca: &CharacterAction = menu.get_option(s).unwrap();
-            if let CharacterAction::Index(id) = ca {
-                return action_enc.get(id);
-            }
-            if let CharacterAction::Literal(a) = ca {
+        // Reroll a few times when landing on something unusable
+        for _ in 0..Character::RANDOM_ACTION_TRIES {
+            let mut menu: &ActionMenu = &self.actions; // ROOT ActionMenu
+            let action = loop {
+                if menu.is_empty() {
+                    break None;
+                }
+                let s = rng.gen_range(0..menu.len());
+                match menu.get_option(s).unwrap() {
+                    CharacterAction::Index(id) => break action_enc.get(id),
+                    CharacterAction::Literal(a) => break Some(a),
+                    CharacterAction::Menu(m) => menu = m,
+                    CharacterAction::UseItem | CharacterAction::Run => break None,
+                }
+            };
+            if let Some(a) = action.filter(|a| self.can_afford_action_costs(a)) {
                 return Some(a);
             }
-            if let CharacterAction::Menu(m) = ca {
-                menu = m;
-                continue;
-            }
         }
         None
     }
-    pub fn dclock(
+    pub fn dclock(&self, dt: u16, statblocks: &StatBlockEncyclopedia) -> u16 {
+        if self.is_down() {
+            return 0;
+        }
+        // AddMods can take Speed below 0, which stops the clock
+        let speed = self
+            .get_stat_val(String::from("Speed"), 0, statblocks)
+            .max(0);
+        dt.saturating_mul(u16::try_from(speed).unwrap_or(u16::MAX))
+    }
+    pub fn experience_conditions(
         &mut self,
         dt: u16,
         conditions: &ConditionEncyclopedia,
-        statblocks: &StatBlockEncyclopedia,
-    ) -> u16 {
-        // FIXME This is almost certainly error-prone ???
+    ) -> ConditionTriggers {
+        let mut triggers = ConditionTriggers::new();
         if self.is_down() {
-            0
-        } else {
-            self.experience_conditions(dt, conditions);
-            dt.saturating_mul(
-                u16::try_from(self.get_stat_val(String::from("Speed"), 0, statblocks))
-                    .ok()
-                    .unwrap(),
-            )
+            return triggers;
         }
-    }
-    fn experience_conditions(&mut self, dt: u16, conditions: &ConditionEncyclopedia) {
-        let mut expired_condition_indices = Vec::<usize>::new();
-        for (idx, tcon) in self.conditions.iter_mut().enumerate() {
+        for tcon in self.conditions.iter_mut() {
             let con = conditions
                 .get(&tcon.condition_id)
                 .expect("missing condition in encyclopedia");
-            for (reff, reff_cd) in con
-                .repeat_effects
-                .iter()
-                .zip(tcon.repeat_effect_countdowns.iter_mut())
-            {
-                *reff_cd = reff_cd.saturating_sub(dt);
-                if *reff_cd == 0 {
-                    // TODO reff.rep: IndexOrLiteral<Effect>
-                    *reff_cd = reff.period;
-                }
+            triggers.append(&mut tcon.experience(dt, con));
+        }
+        self.conditions.retain(|tcon| !tcon.is_expired());
+        self.wear_off_buffs(dt);
+        triggers
+    }
+    // Buffs wear off like Conditions do
+    fn wear_off_buffs(&mut self, dt: u16) {
+        for buff in self.buffs.iter_mut() {
+            buff.duration = buff.duration.saturating_sub(dt);
+        }
+        self.buffs.retain(|buff| buff.duration > 0);
+    }
+    /// Runs down Conditions and buffs without triggering anything, e.g. on a
+    /// copy to see how stats will change
+    pub fn wear_off(&mut self, dt: u16) {
+        for tcon in self.conditions.iter_mut() {
+            if let Some(duration) = tcon.duration.as_mut() {
+                *duration = duration.saturating_sub(dt);
             }
-            for (rhit, rhit_cd) in con
-                .repeat_hits
-                .iter()
-                .zip(tcon.repeat_hit_countdowns.iter_mut())
-            {
-                *rhit_cd = rhit_cd.saturating_sub(dt);
-                if *rhit_cd == 0 {
-                    // TODO rhit.rep: Hit
-                    *rhit_cd = rhit.period;
-                }
+        }
+        self.conditions.retain(|tcon| !tcon.is_expired());
+        self.wear_off_buffs(dt);
+    }
+    /// How long until the next Condition or buff wears off
+    pub fn next_wear_off(&self) -> Option<u16> {
+        self.conditions
+            .iter()
+            .filter_map(|tcon| tcon.duration)
+            .chain(self.buffs.iter().map(|buff| buff.duration))
+            .filter(|duration| *duration > 0)
+            .min()
+    }
+    pub fn add_buff(&mut self, buff: Buff) {
+        self.buffs.push(buff);
+    }
+    fn get_stat_mods(&self, stat_name: &Name) -> Vec<StatMod> {
+        let mut mods = Vec::<StatMod>::new();
+        for item in self.equips.values().flatten() {
+            if let Some(m) = item.get_stat_mod(stat_name) {
+                mods.push(m);
             }
-            tcon.duration += dt;
-            if let Some(max_duration) = con.duration {
-                if tcon.duration >= max_duration {
-                    expired_condition_indices.insert(idx, 0);
-                }
+        }
+        for tcon in &self.conditions {
+            if let Some(m) = tcon.mods.get(stat_name) {
+                mods.push(*m);
             }
         }
-        for idx in expired_condition_indices.iter() {
-            // todo
+        for buff in &self.buffs {
+            if &buff.stat == stat_name {
+                mods.push(buff.mods);
+            }
         }
+        mods
     }
     pub fn sum_add_mods(&self, stat_name: Name) -> Stat {
-        let _ = stat_name;
-        0 // todo
+        stats::sum_add_mods(self.get_stat_mods(&stat_name).iter())
     }
     pub fn sum_mult_mods(&self, stat_name: Name) -> Stat {
-        let _ = stat_name;
-        1 // todo
+        stats::sum_mult_mods(self.get_stat_mods(&stat_name).iter())
     }
     pub fn can_afford_action_costs(&self, action: &Action) -> bool {
         for (pool, cost) in action.costs_iter() {
@@ -326,12 +516,13 @@ impl Character {
             pool.current -= *cost;
         }
     }
-    pub fn use_action_on(
+    pub fn use_action_on<R: Rng>(
         &mut self,
         action: &Action,
         target: &Character,
         effect_enc: &EffectEncyclopedia,
         statblocks: &StatBlockEncyclopedia,
+        rng: &mut R,
     ) -> Hits {
         self.spend_action_costs(action);
         let mut hits = Hits::new();
@@ -340,7 +531,8 @@ impl Character {
                 &mut effect_enc
                     .resolve(effect)
                     .unwrap()
-                    .actor_affect_target(self, target, statblocks),
+                    .actor_affect_target(self, target, statblocks, rng)
+                    .1,
             );
         }
         hits
@@ -348,11 +540,85 @@ impl Character {
     pub fn is_down(&self) -> bool {
         //todo critical_pools attribute
         self.get_pool_vals(String::from("HP")).unwrap().0 <= 0
+            || self.conditions.iter().any(|tcon| tcon.down)
     }
-    pub fn take_condition(&mut self, cond: &Condition) -> bool {
-        // TODO
+    pub fn has_condition(&self, condition_id: Id) -> bool {
+        self.conditions
+            .iter()
+            .any(|tcon| tcon.condition_id == condition_id)
+    }
+    pub fn take_condition(&mut self, condition_id: Id, cond: &Condition) -> bool {
+        // Conditions don't stack
+        if self.has_condition(condition_id) {
+            return false;
+        }
+        self.conditions
+            .push(TargetCondition::new(condition_id, cond));
         true
     }
+    pub fn cure_conditions(
+        &mut self,
+        traits: &Traits,
+        conditions: &ConditionEncyclopedia,
+    ) -> Vec<Id> {
+        let mut cured = Vec::<Id>::new();
+        self.conditions.retain(|tcon| {
+            let con = conditions
+                .get(&tcon.condition_id)
+                .expect("missing condition in encyclopedia");
+            if con.is_removed_by(traits) {
+                cured.push(tcon.condition_id);
+                return false;
+            }
+            true
+        });
+        cured
+    }
+    fn affinity_sources(&self) -> impl Iterator<Item = &Affinities> {
+        std::iter::once(&self.affinities)
+            .chain(self.equips.values().flatten().map(|item| &item.affinities))
+            .chain(self.conditions.iter().map(|tcon| &tcon.affinities))
+    }
+    /// The strongest Affinity to any of the traits, from the Character,
+    /// their equipment and Conditions
+    pub fn get_affinity(&self, traits: &Traits) -> Option<Affinity> {
+        self.affinity_sources()
+            .flat_map(|affinities| traits.iter().filter_map(|t| affinities.get(t)))
+            .max()
+            .copied()
+    }
+    /// Every trait the Character has an Affinity to, e.g. for a Scan
+    pub fn get_affinities(&self) -> Vec<(Name, Affinity)> {
+        let mut traits: Vec<&Name> = self
+            .affinity_sources()
+            .flat_map(|affinities| affinities.keys())
+            .collect();
+        traits.sort();
+        traits.dedup();
+        traits
+            .into_iter()
+            .filter_map(|t| {
+                let affinity = self.get_affinity(&vec![t.clone()])?;
+                Some((t.clone(), affinity))
+            })
+            .collect()
+    }
+    /// Reactions from equipment, then from Conditions
+    pub fn get_reactions<'a>(&'a self, conditions: &'a ConditionEncyclopedia) -> Vec<&'a Reaction> {
+        let mut reactions: Vec<&Reaction> = self
+            .equips
+            .values()
+            .flatten()
+            .flat_map(|item| item.reactions.iter())
+            .collect();
+        for tcon in &self.conditions {
+            let con = conditions
+                .get(&tcon.condition_id)
+                .expect("missing condition in encyclopedia");
+            reactions.extend(con.reactions.iter());
+        }
+        reactions
+    }
 }
 
 impl fmt::Display for Character {
@@ -442,7 +708,7 @@ mod tests {
         let mog = characters.get(&10).unwrap();
         let mut selections = Vec::<usize>::new();
         let mog_menus = mog.get_action_options(&selections, &actions);
-        let mut expected_menus = vec![vec!["Attack", "Dance", "Magic", "Item"]];
+        let mut expected_menus = vec![vec!["Attack", "Dance", "Magic", "Item", "Run"]];
         assert_eq!(&mog_menus, &expected_menus);
         selections.push(1); // select "Dance"
         expected_menus.push(vec!["Water Harmony", "Desert Lullaby"]);
@@ -464,6 +730,208 @@ mod tests {
         assert_eq!(selected_action.copy_name(), "Water Harmony");
     }
     #[test]
+    fn take_and_cure_condition_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let mut c = Character::new(0, String::from("new character"));
+        let poison_id = conditions.find_by_name("Poison").unwrap();
+        let poison = conditions.get(&poison_id).unwrap();
+        assert!(c.take_condition(poison_id, poison));
+        assert!(!c.take_condition(poison_id, poison));
+        assert!(c.has_condition(poison_id));
+        assert!(!c.is_down());
+        let cured = c.cure_conditions(&vec![String::from("Revive")], &conditions);
+        assert!(cured.is_empty());
+        let cured = c.cure_conditions(&vec![String::from("Antidote")], &conditions);
+        assert_eq!(cured, vec![poison_id]);
+        assert!(!c.has_condition(poison_id));
+        let ko_id = conditions.find_down().unwrap();
+        assert!(c.take_condition(ko_id, conditions.get(&ko_id).unwrap()));
+        assert!(c.is_down());
+        c.cure_conditions(&vec![String::from("Revive")], &conditions);
+        assert!(!c.is_down());
+    }
+    #[test]
+    fn stat_mods_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut c = Character::new(0, String::from("new character"));
+        let speed = c.get_stat_val(String::from("Speed"), 0, &statblocks);
+        assert_eq!(c.sum_add_mods(String::from("Speed")), 0);
+        assert_eq!(c.sum_mult_mods(String::from("Speed")), 100);
+        let haste_id = conditions.find_by_name("Haste").unwrap();
+        c.take_condition(haste_id, conditions.get(&haste_id).unwrap());
+        assert_eq!(c.sum_mult_mods(String::from("Speed")), 150);
+        assert_eq!(
+            c.get_stat_val(String::from("Speed"), 0, &statblocks),
+            speed * 3 / 2
+        );
+        c.add_buff(Buff {
+            stat: String::from("Speed"),
+            mods: StatMod::new(2, 100),
+            duration: 10,
+        });
+        assert_eq!(c.sum_add_mods(String::from("Speed")), 2);
+        assert_eq!(
+            c.get_stat_val(String::from("Speed"), 0, &statblocks),
+            speed * 3 / 2 + 2
+        );
+        c.experience_conditions(10, &conditions);
+        assert!(c.buffs.is_empty());
+        assert_eq!(c.sum_add_mods(String::from("Speed")), 0);
+        // Haste is all that's left
+        let left = c.next_wear_off().unwrap();
+        let mut copy = c.clone();
+        copy.wear_off(left - 1);
+        assert_eq!(copy.next_wear_off(), Some(1));
+        copy.wear_off(1);
+        assert!(copy.next_wear_off().is_none());
+        assert_eq!(
+            copy.get_stat_val(String::from("Speed"), 0, &statblocks),
+            speed
+        );
+        assert!(c.has_condition(haste_id));
+    }
+    #[test]
+    fn affinities_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let mut c = Character::from_json(
+            r#"{"id": 0, "name": "Test", "affinities": {"Fire": "Weak", "Magic": "Weak"}}"#,
+        );
+        let fire = vec![String::from("Fire")];
+        assert_eq!(c.get_affinity(&fire), Some(Affinity::Weak));
+        let shield = Item::from_json(
+            r#"{"id": 105, "name": "Flame Shield", "slots": ["Shield"],
+                "affinities": {"Fire": "Absorb", "Ice": "Weak"}}"#,
+        );
+        c.equip_to_slot(shield, String::from("Shield")).unwrap();
+        assert_eq!(c.get_affinity(&fire), Some(Affinity::Absorb));
+        let shell_id = conditions.find_by_name("Shell").unwrap();
+        c.take_condition(shell_id, conditions.get(&shell_id).unwrap());
+        assert_eq!(
+            c.get_affinities(),
+            vec![
+                (String::from("Fire"), Affinity::Absorb),
+                (String::from("Ice"), Affinity::Weak),
+                (String::from("Magic"), Affinity::Resist),
+            ]
+        );
+        assert!(c.get_affinity(&vec![String::from("Physical")]).is_none());
+    }
+    #[test]
+    fn negative_speed_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
+        assert_eq!(c.dclock(2, &statblocks), 20);
+        let anchor = Item::from_json(
+            r#"{"id": 1, "name": "Anchor", "slots": ["Weapon"],
+                "mods": {"Speed": {"AddMod": -15}}}"#,
+        );
+        c.equip_to_slot(anchor, String::from("Weapon")).unwrap();
+        assert!(c.get_stat_val(String::from("Speed"), 0, &statblocks) < 0);
+        assert_eq!(c.dclock(2, &statblocks), 0);
+        c.unequip_from_slot(String::from("Weapon")).unwrap();
+        c.add_buff(Buff {
+            stat: String::from("Speed"),
+            mods: StatMod::new(-11, 100),
+            duration: 10,
+        });
+        assert_eq!(c.dclock(2, &statblocks), 0);
+    }
+    #[test]
+    fn equip_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut c = Character::from_json(r#"{"id": 0, "name": "Test", "class": "Knight"}"#);
+        let offense = c.get_stat_val(String::from("Offense"), 0, &statblocks);
+        let sword = Item::from_json(
+            r#"{"id": 1, "name": "Sword", "power": 7, "slots": ["Weapon"],
+                "mods": {"Speed": {"AddMod": -1}}, "classes": ["Knight"]}"#,
+        );
+        assert_eq!(
+            c.get_item_attr(String::from("Weapon"), String::from("Power")),
+            Some(0)
+        );
+        assert!(c
+            .equip_to_slot(sword.clone(), String::from("Weapon"))
+            .unwrap()
+            .is_none());
+        assert_eq!(c.get_equipped(&String::from("Weapon")), Some(&sword));
+        assert_eq!(
+            c.get_item_attr(String::from("Weapon"), String::from("Power")),
+            Some(7)
+        );
+        assert_eq!(
+            c.get_stat_val(String::from("Offense"), 0, &statblocks),
+            offense + 7
+        );
+        assert_eq!(c.sum_add_mods(String::from("Speed")), -1);
+        // Wrong slot and missing slot
+        assert!(matches!(
+            c.can_equip(&sword, &String::from("Shield")),
+            Err(EquipError::WrongSlot { .. })
+        ));
+        assert_eq!(
+            c.equip_to_slot(sword.clone(), String::from("Hat")),
+            Err((
+                EquipError::NoSuchSlot(String::from("Hat")),
+                Box::new(sword.clone())
+            ))
+        );
+        // Swapping hands back the previous equip
+        assert_eq!(
+            c.equip_to_slot(sword.clone(), String::from("Weapon")),
+            Ok(Some(sword.clone()))
+        );
+        assert_eq!(c.unequip_from_slot(String::from("Weapon")), Ok(Some(sword)));
+        assert_eq!(c.unequip_from_slot(String::from("Weapon")), Ok(None));
+        assert_eq!(
+            c.get_stat_val(String::from("Offense"), 0, &statblocks),
+            offense
+        );
+        // Class restriction
+        let mut c = Character::new(0, String::from("Classless"));
+        let sword = Item::from_json(
+            r#"{"id": 1, "name": "Sword", "slots": ["Weapon"], "classes": ["Knight"]}"#,
+        );
+        assert!(matches!(
+            c.equip_to_slot(sword, String::from("Weapon")),
+            Err((EquipError::WrongClass { .. }, _))
+        ));
+    }
+    #[test]
+    fn experience_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut c = Character::new(0, String::from("Test"));
+        c.growth = Some(
+            serde_json::from_str(
+                r#"{"thresholds": [10, 30, 60], "stats": {"Strength": 2}, "pools": {"HP": 5}}"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(c.get_level(), 1);
+        assert_eq!(c.gain_experience(9, &statblocks), 0);
+        assert_eq!(c.gain_experience(25, &statblocks), 2);
+        assert_eq!(c.get_experience(), 34);
+        assert_eq!(c.get_level(), 3);
+        assert_eq!(c.get_base_stat(String::from("Strength")), Some(&14));
+        assert_eq!(c.get_pool_vals(String::from("HP")), Some((20, 20)));
+        // The table tops out at level 4
+        assert_eq!(c.gain_experience(1000, &statblocks), 1);
+        assert_eq!(c.get_level(), 4);
+        // Characters without any growth never level up
+        let mut c = Character::from_json(r#"{"id": 0, "name": "Test", "stats": 1}"#);
+        assert_eq!(c.gain_experience(1000, &statblocks), 0);
+        assert_eq!(c.get_experience(), 1000);
+    }
+    #[test]
+    fn steal_test() {
+        let mut c = Character::from_json(
+            r#"{"id": 0, "name": "Test", "steals": [{"item": 0, "chance": 0}, {"item": 1}]}"#,
+        );
+        let mut rng = rand::thread_rng();
+        assert_eq!(c.steal_from(&mut rng), Some((1, 1)));
+        assert_eq!(c.steal_from(&mut rng), None);
+    }
+    #[test]
     #[should_panic]
     fn bad_pool_test() {
         let c = Character::new(0, String::from("new character"));
@@ -1,12 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+pub use crate::formula::{Formula, HitFormula};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum PlayerType {
     NPC,
     PC,
 }
 
-pub type Formula = String;
 pub type Id = u64; // Conform to serde_json::Value
 pub type Name = String;
 
@@ -27,7 +28,7 @@ pub enum IndexedOrLiteral<T> {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum HitAmt {
     Constant(i32),
-    Formula(Formula),
+    Formula(HitFormula),
     //todo    StandardFormula(StandardFormula),
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
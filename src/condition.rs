@@ -1,12 +1,13 @@
-use std::collections::HashMap;
-
 use serde::{Deserialize, Serialize};
 
+use crate::affinity::Affinities;
 use crate::common::*;
 use crate::effect::{Effect, Traits};
-use crate::stats::Stat;
+use crate::encyclopedia::ConditionEncyclopedia;
+use crate::reaction::Reaction;
+use crate::stats::StatMods;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Repeat<T> {
     pub rep: T,
     pub period: u16,
@@ -16,6 +17,34 @@ pub struct Repeat<T> {
 type RepeatEffects = Vec<Repeat<IndexedOrLiteral<Effect>>>;
 type RepeatHits = Vec<Repeat<Hit>>;
 
+// Counts down each Repeat by dt, returning the ones that went off
+fn tick_repeats<T: Clone>(
+    repeats: &[Repeat<T>],
+    countdowns: &mut Vec<u16>,
+    counts: &mut Vec<u16>,
+    dt: u16,
+) -> Vec<T> {
+    countdowns.resize(repeats.len(), 0);
+    counts.resize(repeats.len(), 0);
+    let mut fired = Vec::<T>::new();
+    for ((rep, countdown), count) in repeats
+        .iter()
+        .zip(countdowns.iter_mut())
+        .zip(counts.iter_mut())
+    {
+        if rep.number.is_some_and(|number| *count >= number) {
+            continue;
+        }
+        *countdown = countdown.saturating_sub(dt);
+        if *countdown == 0 {
+            fired.push(rep.rep.clone());
+            *count += 1;
+            *countdown = rep.period;
+        }
+    }
+    fired
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 enum Visual {
     // https://docs.rs/termion/latest/termion/color/struct.AnsiValue.html
@@ -33,25 +62,174 @@ pub struct Condition {
     //stopped_by: Traits, // Conditions?
     #[serde(default)]
     removed_by: Traits, // Effects that have one of these traits will remove the Condition
+    #[serde(default)]
+    down: bool, // Characters with this Condition are down, e.g. K.O.
     //repeat_actions: RepeatActions, // needs targeting
     #[serde(default)]
     pub repeat_effects: RepeatEffects,
     #[serde(default)]
     pub repeat_hits: RepeatHits,
     #[serde(default)]
-    mods: HashMap<Name, HashMap<Name, Stat>>, // e.g. {"clock": {"MultMod2": 0}} for K.O.
-    //reactions: Map::<Trait, RelativeTargetedEffect>, // e.g. counter, reflect
+    mods: StatMods, // e.g. {"Speed": {"MultMod": 200}} for Haste
+    #[serde(default)]
+    pub reactions: Vec<Reaction>, // e.g. counter, reflect
+    #[serde(default)]
+    affinities: Affinities, // e.g. {"Magic": "Resist"} for Shell
     #[serde(default)]
     play_override: Option<PlayerType>,
     #[serde(default)]
     visual: Option<Visual>,
 }
 
+impl Condition {
+    pub fn copy_name(&self) -> Name {
+        self.name.clone()
+    }
+    pub fn is_down(&self) -> bool {
+        self.down
+    }
+    pub fn is_removed_by(&self, traits: &Traits) -> bool {
+        self.removed_by.iter().any(|t| traits.contains(t))
+    }
+}
+
+impl ConditionEncyclopedia {
+    pub fn find_by_name(&self, name: &str) -> Option<Id> {
+        self.en
+            .iter()
+            .find(|(_, con)| con.name == name)
+            .map(|(id, _)| *id)
+    }
+    pub fn find_down(&self) -> Option<Id> {
+        self.en.iter().find(|(_, con)| con.down).map(|(id, _)| *id)
+    }
+}
+
+// Something a TargetCondition does as time passes
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConditionTrigger {
+    Effect(Id, Box<IndexedOrLiteral<Effect>>),
+    Hit(Id, Hit),
+    Expired(Id),
+}
+pub type ConditionTriggers = Vec<ConditionTrigger>;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TargetCondition {
-    pub condition_id: Id, // IndexOrLiteral ?
-    pub duration: u16,
+    pub condition_id: Id,      // IndexOrLiteral ?
+    pub duration: Option<u16>, // Remaining; None lasts until removed
     pub repeat_effect_countdowns: Vec<u16>,
     pub repeat_hit_countdowns: Vec<u16>,
+    #[serde(default)]
+    pub repeat_effect_counts: Vec<u16>,
+    #[serde(default)]
+    pub repeat_hit_counts: Vec<u16>,
+    #[serde(default)]
+    pub down: bool,
+    #[serde(default)]
+    pub mods: StatMods,
+    #[serde(default)]
+    pub affinities: Affinities,
 }
 pub type TargetConditions = Vec<TargetCondition>;
+
+impl TargetCondition {
+    pub fn new(condition_id: Id, con: &Condition) -> TargetCondition {
+        TargetCondition {
+            condition_id,
+            duration: con.duration,
+            repeat_effect_countdowns: con.repeat_effects.iter().map(|r| r.period).collect(),
+            repeat_hit_countdowns: con.repeat_hits.iter().map(|r| r.period).collect(),
+            repeat_effect_counts: vec![0; con.repeat_effects.len()],
+            repeat_hit_counts: vec![0; con.repeat_hits.len()],
+            down: con.down,
+            mods: con.mods.clone(),
+            affinities: con.affinities.clone(),
+        }
+    }
+    pub fn experience(&mut self, dt: u16, con: &Condition) -> ConditionTriggers {
+        let id = self.condition_id;
+        let mut triggers = ConditionTriggers::new();
+        for effect in tick_repeats(
+            &con.repeat_effects,
+            &mut self.repeat_effect_countdowns,
+            &mut self.repeat_effect_counts,
+            dt,
+        ) {
+            triggers.push(ConditionTrigger::Effect(id, Box::new(effect)));
+        }
+        for hit in tick_repeats(
+            &con.repeat_hits,
+            &mut self.repeat_hit_countdowns,
+            &mut self.repeat_hit_counts,
+            dt,
+        ) {
+            triggers.push(ConditionTrigger::Hit(id, hit));
+        }
+        if let Some(duration) = self.duration.as_mut() {
+            *duration = duration.saturating_sub(dt);
+            if *duration == 0 {
+                triggers.push(ConditionTrigger::Expired(id));
+            }
+        }
+        triggers
+    }
+    pub fn is_expired(&self) -> bool {
+        self.duration == Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_by_name_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let id = conditions.find_by_name("Poison").unwrap();
+        assert_eq!(conditions.get(&id).unwrap().copy_name(), "Poison");
+        assert!(conditions.find_by_name("Moxie").is_none());
+        let id = conditions.find_down().unwrap();
+        assert!(conditions.get(&id).unwrap().is_down());
+    }
+    #[test]
+    fn removed_by_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let poison = conditions
+            .get(&conditions.find_by_name("Poison").unwrap())
+            .unwrap();
+        assert!(poison.is_removed_by(&vec![String::from("Antidote")]));
+        assert!(!poison.is_removed_by(&vec![String::from("Revive")]));
+        assert!(!poison.is_removed_by(&Traits::new()));
+    }
+    #[test]
+    fn target_condition_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        let tcon = TargetCondition::new(2, conditions.get(&2).unwrap());
+        assert_eq!(tcon.duration, None);
+        assert_eq!(tcon.repeat_hit_countdowns, vec![10]);
+        assert!(tcon.repeat_effect_countdowns.is_empty());
+        assert!(!tcon.down);
+    }
+    #[test]
+    fn experience_test() {
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        // Test Condition RepeatHit: every 10, once
+        let con = conditions.get(&2).unwrap();
+        let mut tcon = TargetCondition::new(2, con);
+        assert!(tcon.experience(9, con).is_empty());
+        let triggers = tcon.experience(1, con);
+        assert_eq!(triggers.len(), 1);
+        assert!(matches!(triggers[0], ConditionTrigger::Hit(2, _)));
+        assert!(tcon.experience(10, con).is_empty());
+        assert!(!tcon.is_expired());
+        // Burn: expires
+        let burn_id = conditions.find_by_name("Burn").unwrap();
+        let con = conditions.get(&burn_id).unwrap();
+        let mut tcon = TargetCondition::new(burn_id, con);
+        let max_duration = tcon.duration.unwrap();
+        let triggers = tcon.experience(max_duration, con);
+        assert_eq!(triggers.last(), Some(&ConditionTrigger::Expired(burn_id)));
+        assert!(tcon.is_expired());
+    }
+}
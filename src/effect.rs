@@ -1,14 +1,41 @@
+use rand::Rng;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
 use crate::character::Character;
 use crate::common::*;
-use crate::encyclopedia::StatBlockEncyclopedia;
+use crate::encyclopedia::{ConditionEncyclopedia, EffectEncyclopedia, StatBlockEncyclopedia};
 use crate::formula;
+use crate::formula::{FormulaError, HitFormula};
+use crate::stats::Buffs;
 
 pub type Traits = Vec<Name>;
 
+/// A chance of an Effect's hits landing harder
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Critical {
+    // Percent chance, a hit formula, e.g. "3"
+    pub chance: HitFormula,
+    // Percent of the usual amount
+    #[serde(default = "Critical::default_multiplier")]
+    pub multiplier: i32,
+}
+
+impl Critical {
+    fn default_multiplier() -> i32 {
+        200
+    }
+}
+
+/// How an Effect turned out against a target
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Landing {
+    Hit,
+    Critical,
+    Miss,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Effect {
     id: Id,
@@ -16,10 +43,22 @@ pub struct Effect {
     name: Name,
     #[serde(default)]
     pub hits: Hits,
+    // Condition names, with the percent chance of inflicting them
+    #[serde(default)]
+    pub conditions: Hits,
+    #[serde(default)]
+    pub traits: Traits,
+    #[serde(default)]
+    pub buffs: Buffs,
+    // Percent chance of landing, a hit formula, e.g. "- 100 $Evasion";
+    // None always lands
     #[serde(default)]
-    conditions: Hits,
+    pub accuracy: Option<HitFormula>,
     #[serde(default)]
-    traits: Traits,
+    pub critical: Option<Critical>,
+    // Reveals the target's Affinities
+    #[serde(default)]
+    pub scan: bool,
     #[serde(default = "Effect::default_msg")]
     msg: String,
 }
@@ -32,6 +71,10 @@ impl Effect {
             hits: Hits::new(),
             conditions: Hits::new(),
             traits: Traits::new(),
+            buffs: Buffs::new(),
+            accuracy: None,
+            critical: None,
+            scan: false,
             msg: String::from(""),
         }
     }
@@ -41,17 +84,97 @@ impl Effect {
     pub fn whoami(&self) -> (Id, &str) {
         (self.id, &self.name[..])
     }
-    pub fn actor_affect_target(
+    // A format string that may use {:actor}, {:target}, {:effect}, or {:item}
+    pub fn get_message(&self, actor: &str, target: &str, item: &str) -> String {
+        self.msg
+            .replace("{:actor}", actor)
+            .replace("{:target}", target)
+            .replace("{:effect}", &self.name)
+            .replace("{:item}", item)
+    }
+    /// Checks that the formulas only refer to stats and pools the StatBlocks declare
+    pub fn check_stats(&self, statblocks: &StatBlockEncyclopedia) -> Result<(), FormulaError> {
+        for hit in self.hits.iter().chain(&self.conditions) {
+            if let HitAmt::Formula(f) = &hit.amount {
+                f.check_stats(statblocks)?;
+            }
+        }
+        let chances = self
+            .accuracy
+            .iter()
+            .chain(self.critical.iter().map(|c| &c.chance));
+        for f in chances {
+            f.check_stats(statblocks)?;
+        }
+        Ok(())
+    }
+    /// Rolls for whether the Effect misses, lands, or lands a critical hit
+    pub fn roll_landing<R: Rng>(
         &self,
         actor: &Character,
         target: &Character,
         statblocks: &StatBlockEncyclopedia,
+        rng: &mut R,
+    ) -> Landing {
+        let mut roll = |chance: &HitFormula| {
+            let chance = formula::eval_hit(chance, Some(actor), target, statblocks, rng);
+            rng.gen_range(0..100) < chance
+        };
+        if self.accuracy.as_ref().is_some_and(|f| !roll(f)) {
+            Landing::Miss
+        } else if self.critical.as_ref().is_some_and(|c| roll(&c.chance)) {
+            Landing::Critical
+        } else {
+            Landing::Hit
+        }
+    }
+    /// The hits of the Effect on the target, none if it missed
+    pub fn actor_affect_target<R: Rng>(
+        &self,
+        actor: &Character,
+        target: &Character,
+        statblocks: &StatBlockEncyclopedia,
+        rng: &mut R,
+    ) -> (Landing, Hits) {
+        let landing = self.roll_landing(actor, target, statblocks, rng);
+        if landing == Landing::Miss {
+            return (landing, Hits::new());
+        }
+        let mut hits = Effect::eval_hits(&self.hits, actor, target, statblocks, rng);
+        let multiplier = match (landing, &self.critical) {
+            (Landing::Critical, Some(critical)) => critical.multiplier,
+            _ => 100,
+        };
+        let affinity = target.get_affinity(&self.traits);
+        for hit in hits.iter_mut() {
+            if let HitAmt::Constant(amount) = hit.amount {
+                let amount = amount.saturating_mul(multiplier) / 100;
+                hit.amount = HitAmt::Constant(affinity.map_or(amount, |a| a.apply(amount)));
+            }
+        }
+        (landing, hits)
+    }
+    pub fn actor_afflict_target<R: Rng>(
+        &self,
+        actor: &Character,
+        target: &Character,
+        statblocks: &StatBlockEncyclopedia,
+        rng: &mut R,
+    ) -> Hits {
+        Effect::eval_hits(&self.conditions, actor, target, statblocks, rng)
+    }
+    fn eval_hits<R: Rng>(
+        unevaluated: &Hits,
+        actor: &Character,
+        target: &Character,
+        statblocks: &StatBlockEncyclopedia,
+        rng: &mut R,
     ) -> Hits {
         let mut hits = Hits::new();
-        for hit in &self.hits {
+        for hit in unevaluated {
             let amount: i32 = match &hit.amount {
                 HitAmt::Constant(v) => *v,
-                HitAmt::Formula(f) => formula::eval_hit(f, Some(actor), target, statblocks),
+                HitAmt::Formula(f) => formula::eval_hit(f, Some(actor), target, statblocks, rng),
             };
             //            let v: i32 = target.hit_pool(&hit.pool, amount);
             hits.push(Hit {
@@ -81,10 +204,38 @@ impl fmt::Display for Effect {
     }
 }
 
+impl EffectEncyclopedia {
+    /// Hands back the first Effect with a formula that refers to an undeclared stat
+    pub fn check_stats(
+        &self,
+        statblocks: &StatBlockEncyclopedia,
+    ) -> Result<(), (Id, FormulaError)> {
+        for (id, effect) in &self.en {
+            effect.check_stats(statblocks).map_err(|e| (*id, e))?;
+        }
+        Ok(())
+    }
+    /// Hands back the first Effect that inflicts a Condition the
+    /// ConditionEncyclopedia doesn't have, with the Condition's name
+    pub fn check_conditions(&self, conditions: &ConditionEncyclopedia) -> Result<(), (Id, Name)> {
+        for (id, effect) in &self.en {
+            if let Some(hit) = effect
+                .conditions
+                .iter()
+                .find(|hit| conditions.find_by_name(&hit.pool).is_none())
+            {
+                return Err((*id, hit.pool.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::character::Character;
+    use crate::stats::{Buff, StatMod};
 
     #[test]
     fn new_test() {
@@ -93,6 +244,15 @@ mod tests {
         assert_eq!(effect.whoami(), (id, name));
     }
     #[test]
+    fn get_message_test() {
+        let mut effect = Effect::new(0, String::from("Jerky"));
+        effect.msg = String::from("{:actor} fed {:target} {:item} ({:effect}).");
+        assert_eq!(
+            effect.get_message("Mog", "Umaro", "Fabulous Jerky"),
+            "Mog fed Umaro Fabulous Jerky (Jerky)."
+        );
+    }
+    #[test]
     fn actor_affect_test() {
         let statblocks = StatBlockEncyclopedia::new("data/stats.json");
         let c = Character::new(0, String::from("Test Character"));
@@ -108,10 +268,12 @@ mod tests {
             },
             Hit {
                 pool: String::from("HP"),
-                amount: HitAmt::Formula(f),
+                amount: HitAmt::Formula(f.parse().unwrap()),
             },
         ];
-        let hits = effect.actor_affect_target(&c, &t, &statblocks);
+        let (landing, hits) =
+            effect.actor_affect_target(&c, &t, &statblocks, &mut rand::thread_rng());
+        assert_eq!(landing, Landing::Hit);
         assert_eq!(effect.hits[0].pool, hits[0].pool);
         assert_eq!(effect.hits[1].pool, hits[1].pool);
         assert_eq!(hits[0].amount, HitAmt::Constant(v));
@@ -119,4 +281,69 @@ mod tests {
         //        let hp = t.get_pool_vals("HP".to_string()).unwrap().0;
         //        assert_eq!(hp, init_hp - 2);
     }
+    #[test]
+    fn landing_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let c = Character::new(0, String::from("Test Character"));
+        let mut t = Character::new(1, String::from("Test Target Character"));
+        let dodger =
+            Character::from_json(r#"{"id": 2, "name": "Dodger", "base_stats": {"Evasion": 100}}"#);
+        let mut effect: Effect = serde_json::from_str(
+            r#"{"id": 0, "name": "Test Effect",
+                "hits": [{"pool": "HP", "amount": {"Constant": 10}}],
+                "accuracy": "- 100 $Evasion",
+                "critical": {"chance": "100", "multiplier": 300}}"#,
+        )
+        .unwrap();
+        let mut rng = rand::thread_rng();
+        let (landing, hits) = effect.actor_affect_target(&c, &dodger, &statblocks, &mut rng);
+        assert_eq!(landing, Landing::Miss);
+        assert!(hits.is_empty());
+        let (landing, hits) = effect.actor_affect_target(&c, &t, &statblocks, &mut rng);
+        assert_eq!(landing, Landing::Critical);
+        assert_eq!(hits[0].amount, HitAmt::Constant(30));
+        // Evasion is a derived stat, so buffs to it count too
+        t.add_buff(Buff {
+            stat: String::from("Evasion"),
+            mods: StatMod::new(100, 100),
+            duration: 10,
+        });
+        assert_eq!(
+            effect.roll_landing(&c, &t, &statblocks, &mut rng),
+            Landing::Miss
+        );
+        effect.critical = None;
+        effect.accuracy = None;
+        assert_eq!(
+            effect.roll_landing(&c, &dodger, &statblocks, &mut rng),
+            Landing::Hit
+        );
+    }
+    #[test]
+    fn affinity_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let c = Character::new(0, String::from("Test Character"));
+        let t = Character::from_json(
+            r#"{"id": 1, "name": "Test Target Character",
+                "affinities": {"Fire": "Weak", "Elemental": "Immune", "Ice": "Absorb"}}"#,
+        );
+        let mut effect = Effect::new(0, "Test Effect".to_string());
+        effect.hits = vec![Hit {
+            pool: String::from("HP"),
+            amount: HitAmt::Constant(10),
+        }];
+        let mut amount = |traits: &[&str]| {
+            effect.traits = traits.iter().map(|t| t.to_string()).collect();
+            effect
+                .actor_affect_target(&c, &t, &statblocks, &mut rand::thread_rng())
+                .1[0]
+                .amount
+                .clone()
+        };
+        assert_eq!(amount(&[]), HitAmt::Constant(10));
+        assert_eq!(amount(&["Fire"]), HitAmt::Constant(20));
+        // The strongest Affinity wins
+        assert_eq!(amount(&["Fire", "Elemental"]), HitAmt::Constant(0));
+        assert_eq!(amount(&["Ice"]), HitAmt::Constant(-10));
+    }
 }
@@ -97,7 +97,12 @@ mod tests {
     }
     #[test]
     fn read_effect_encyclopedia_test() {
-        assert!(!EffectEncyclopedia::new("data/effects.json").is_empty());
+        let effects = EffectEncyclopedia::new("data/effects.json");
+        assert!(!effects.is_empty());
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        assert_eq!(effects.check_stats(&statblocks), Ok(()));
+        let conditions = ConditionEncyclopedia::new("data/conditions.json");
+        assert_eq!(effects.check_conditions(&conditions), Ok(()));
     }
     #[test]
     fn read_item_encyclopedia_test() {
@@ -105,6 +110,8 @@ mod tests {
     }
     #[test]
     fn read_statblocks_encyclopedia_test() {
-        assert!(!StatBlockEncyclopedia::new("data/stats.json").is_empty());
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        assert!(!statblocks.is_empty());
+        assert_eq!(statblocks.check_stats(), Ok(()));
     }
 }
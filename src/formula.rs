@@ -1,181 +1,1317 @@
-use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::character::Character;
-use crate::common::{Formula, Name};
+use crate::common::Name;
 use crate::encyclopedia::StatBlockEncyclopedia;
-use crate::stats::{DerivedStat, Stat};
+use crate::stats::{DerivedStat, Stat, StatBlock};
 
-pub fn eval_stat(stat_name: Name, s: &DerivedStat, c: &Character) -> Stat {
-    let mut tokens = s.split(' ').collect::<VecDeque<_>>();
-    _eval_stat(&mut tokens, c, &stat_name)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FormulaError {
+    Empty,
+    UnexpectedEnd { pos: usize },
+    UnknownToken { pos: usize, token: String },
+    UnexpectedToken { pos: usize, token: String },
+    WrongArgCount { pos: usize, expected: usize },
+    TrailingTokens { pos: usize },
+    // A term that doesn't belong in this kind of formula, e.g. ^Offense in a derived stat
+    NotAllowed { token: String, kind: &'static str },
+    // A stat or pool that isn't declared by the StatBlocks, e.g. $Moxie
+    UnknownStat { token: String },
+    // Reads one way as prefix and another as infix
+    Ambiguous,
+    // Dice with no sides, or too many to roll, e.g. 100000000d6
+    BadDice { pos: usize, token: String },
 }
 
-fn _eval_stat(tokens: &mut VecDeque<&str>, c: &Character, stat_name: &Name) -> Stat {
-    match tokens.pop_front() {
-        Some("+") => {
-            _eval_stat(tokens, c, stat_name).saturating_add(_eval_stat(tokens, c, stat_name))
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormulaError::Empty => write!(f, "empty formula"),
+            FormulaError::UnexpectedEnd { pos } => {
+                write!(f, "formula ran out of tokens at position {}", pos)
+            }
+            FormulaError::UnknownToken { pos, token } => {
+                write!(f, "unknown token '{}' at position {}", token, pos)
+            }
+            FormulaError::UnexpectedToken { pos, token } => {
+                write!(f, "unexpected token '{}' at position {}", token, pos)
+            }
+            FormulaError::WrongArgCount { pos, expected } => {
+                write!(
+                    f,
+                    "function at position {} takes {} arguments",
+                    pos, expected
+                )
+            }
+            FormulaError::TrailingTokens { pos } => {
+                write!(f, "unused tokens starting at position {}", pos)
+            }
+            FormulaError::NotAllowed { token, kind } => {
+                write!(f, "'{}' cannot be used in a {} formula", token, kind)
+            }
+            FormulaError::UnknownStat { token } => {
+                write!(
+                    f,
+                    "'{}' refers to a stat or pool no StatBlock declares",
+                    token
+                )
+            }
+            FormulaError::Ambiguous => {
+                write!(f, "formula means different things as prefix and as infix")
+            }
+            FormulaError::BadDice { pos, token } => {
+                write!(
+                    f,
+                    "dice '{}' at position {} need at least 1 side and at most {} dice",
+                    token, pos, MAX_DICE
+                )
+            }
         }
-        Some("-") => {
-            _eval_stat(tokens, c, stat_name).saturating_sub(_eval_stat(tokens, c, stat_name))
+    }
+}
+
+impl Error for FormulaError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn from_token(token: &str) -> Option<Op> {
+        match token {
+            "+" => Some(Op::Add),
+            "-" => Some(Op::Sub),
+            "*" => Some(Op::Mul),
+            "/" => Some(Op::Div),
+            "<" => Some(Op::Lt),
+            "<=" => Some(Op::Le),
+            ">" => Some(Op::Gt),
+            ">=" => Some(Op::Ge),
+            "==" => Some(Op::Eq),
+            "!=" => Some(Op::Ne),
+            _ => None,
         }
-        Some("*") => {
-            _eval_stat(tokens, c, stat_name).saturating_mul(_eval_stat(tokens, c, stat_name))
+    }
+    fn token(&self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Eq => "==",
+            Op::Ne => "!=",
         }
-        Some("/") => {
-            _eval_stat(tokens, c, stat_name).saturating_div(_eval_stat(tokens, c, stat_name))
+    }
+    fn apply(&self, a: Stat, b: Stat) -> Stat {
+        match self {
+            Op::Add => a.saturating_add(b),
+            Op::Sub => a.saturating_sub(b),
+            Op::Mul => a.saturating_mul(b),
+            // Dividing by 0 gives 0, and Stat::MIN / -1 saturates
+            Op::Div => match a.checked_div(b) {
+                Some(v) => v,
+                None if b == 0 => 0,
+                None => Stat::MAX,
+            },
+            Op::Lt => (a < b) as Stat,
+            Op::Le => (a <= b) as Stat,
+            Op::Gt => (a > b) as Stat,
+            Op::Ge => (a >= b) as Stat,
+            Op::Eq => (a == b) as Stat,
+            Op::Ne => (a != b) as Stat,
         }
-        //        Some("^") => _eval_stat(tokens, c, stat_name).saturating_pow(_eval_stat(tokens, c, stat_name)),
-        Some(term) => _eval_stat_term(term, c, stat_name),
-        None => panic!("Ran out of tokens in _eval_stat"),
     }
 }
 
-fn _eval_stat_term(term: &str, c: &Character, base_stat_name: &Name) -> Stat {
-    if let Ok(v) = term.parse::<Stat>() {
-        return v;
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Func {
+    Min,
+    Max,
+    Clamp,
+    Pow,
+    Rand,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Func> {
+        match name {
+            "min" => Some(Func::Min),
+            "max" => Some(Func::Max),
+            "clamp" => Some(Func::Clamp),
+            "pow" => Some(Func::Pow),
+            "rand" => Some(Func::Rand),
+            _ => None,
+        }
+    }
+    fn arity(&self) -> usize {
+        match self {
+            Func::Clamp => 3,
+            _ => 2,
+        }
     }
-    let tokens = term.split('.').collect::<Vec<_>>();
-    if let Some(item_attr) = c.get_item_attr(Name::from(tokens[0]), Name::from(tokens[1])) {
-        return item_attr;
+    fn name(&self) -> &'static str {
+        match self {
+            Func::Min => "min",
+            Func::Max => "max",
+            Func::Clamp => "clamp",
+            Func::Pow => "pow",
+            Func::Rand => "rand",
+        }
     }
-    let slot_or_stat_name: Name = if tokens[0].is_empty() {
-        Name::from(base_stat_name)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum StatRef {
+    Stat(Name),        // e.g. Magic-Defense
+    PoolCurrent(Name), // e.g. MP.current
+    PoolMax(Name),     // e.g. HP.max
+}
+
+impl StatRef {
+    fn name(&self) -> &Name {
+        match self {
+            StatRef::Stat(name) | StatRef::PoolCurrent(name) | StatRef::PoolMax(name) => name,
+        }
+    }
+    fn resolve(&self, c: &Character, statblocks: &StatBlockEncyclopedia) -> Stat {
+        match self {
+            StatRef::Stat(name) => {
+                let default = if name == "Level" { 1 } else { 0 };
+                c.get_stat_val(name.clone(), default, statblocks)
+            }
+            StatRef::PoolCurrent(name) => c.get_pool_vals(name.clone()).map_or(0, |v| v.0),
+            StatRef::PoolMax(name) => c.get_pool_vals(name.clone()).map_or(0, |v| v.1),
+        }
+    }
+}
+
+impl fmt::Display for StatRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.name().replace(' ', "-");
+        match self {
+            StatRef::Stat(_) => write!(f, "{}", name),
+            StatRef::PoolCurrent(_) => write!(f, "{}.current", name),
+            StatRef::PoolMax(_) => write!(f, "{}.max", name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Term {
+    Constant(Stat),
+    // Hit formulas, e.g. ^Offense, $Magic-Defense or $HP.max
+    Actor(StatRef),
+    Target(StatRef),
+    // Derived stat formulas; None refers to the stat being derived, e.g. "." or ".AddMod"
+    Base(Option<Name>),
+    AddMod(Option<Name>),
+    MultMod(Option<Name>),
+    ItemAttr(Name, Name), // e.g. Weapon.Power
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let owner = |name: &Option<Name>| name.as_deref().unwrap_or("").replace(' ', "-");
+        match self {
+            Term::Constant(v) => write!(f, "{}", v),
+            Term::Actor(stat) => write!(f, "^{}", stat),
+            Term::Target(stat) => write!(f, "${}", stat),
+            Term::Base(name) => write!(f, "{}.", owner(name)),
+            Term::AddMod(name) => write!(f, "{}.AddMod", owner(name)),
+            Term::MultMod(name) => write!(f, "{}.MultMod", owner(name)),
+            Term::ItemAttr(slot, attr) => write!(f, "{}.{}", slot, attr),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Node {
+    Term(Term),
+    Dice(u32, u32), // e.g. 2d6
+    Neg(Box<Node>),
+    BinOp(Op, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+    Cond(Box<Node>, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    // Collects every Node in the tree, parents before their children
+    fn walk<'a>(&'a self, nodes: &mut Vec<&'a Node>) {
+        nodes.push(self);
+        match self {
+            Node::Term(_) | Node::Dice(..) => (),
+            Node::Neg(a) => a.walk(nodes),
+            Node::BinOp(_, a, b) => {
+                a.walk(nodes);
+                b.walk(nodes);
+            }
+            Node::Call(_, args) => args.iter().for_each(|a| a.walk(nodes)),
+            Node::Cond(cond, a, b) => {
+                cond.walk(nodes);
+                a.walk(nodes);
+                b.walk(nodes);
+            }
+        }
+    }
+    // How the Node starts out in a formula, e.g. "rand" or "^Offense"
+    fn token(&self) -> String {
+        match self {
+            Node::Term(term) => term.to_string(),
+            Node::Dice(n, sides) => format!("{}d{}", n, sides),
+            Node::Neg(_) => String::from("-"),
+            Node::BinOp(op, ..) => String::from(op.token()),
+            Node::Call(func, _) => String::from(func.name()),
+            Node::Cond(..) => String::from("?"),
+        }
+    }
+}
+
+fn stat_name(token: &str) -> Name {
+    token.replace('-', " ")
+}
+
+fn optional_stat_name(token: &str) -> Option<Name> {
+    if token.is_empty() {
+        None
     } else {
-        Name::from(tokens[0])
+        Some(stat_name(token))
+    }
+}
+
+fn parse_stat_ref(token: &str) -> Option<StatRef> {
+    let stat_ref = match token.split_once('.') {
+        None => StatRef::Stat(stat_name(token)),
+        Some((pool, "current")) => StatRef::PoolCurrent(stat_name(pool)),
+        Some((pool, "max")) => StatRef::PoolMax(stat_name(pool)),
+        Some(_) => return None,
     };
-    let slot_or_stat_name = slot_or_stat_name.replace('-', " ");
-    if tokens[1].is_empty() {
-        return *c.get_base_stat(slot_or_stat_name).unwrap();
+    match stat_ref {
+        StatRef::Stat(ref name) | StatRef::PoolCurrent(ref name) | StatRef::PoolMax(ref name)
+            if name.is_empty() =>
+        {
+            None
+        }
+        _ => Some(stat_ref),
     }
-    match tokens[1] {
-        "AddMod" => c.sum_add_mods(slot_or_stat_name),
-        "MultMod" => c.sum_mult_mods(slot_or_stat_name),
-        "Power" => c
-            .get_item_attr(slot_or_stat_name, String::from("Power"))
-            .unwrap(),
-        m => panic!("Could not understand statistic attribute {}.", m),
+}
+
+// Dice are rolled one at a time, so a typo like 100000000d6 would hang
+const MAX_DICE: u32 = 1000;
+
+fn parse_dice(pos: usize, token: &str) -> Result<Option<Node>, FormulaError> {
+    let Some((n, sides)) = token.split_once('d') else {
+        return Ok(None);
+    };
+    match (n.parse::<u32>(), sides.parse::<Stat>()) {
+        (Ok(n), Ok(sides)) if n <= MAX_DICE && sides > 0 => Ok(Some(Node::Dice(n, sides as u32))),
+        (Ok(_), Ok(_)) => Err(FormulaError::BadDice {
+            pos,
+            token: token.to_string(),
+        }),
+        _ => Ok(None),
     }
 }
 
-pub fn eval_hit(
-    f: &Formula,
-    actor: Option<&Character>,
-    target: &Character,
-    statblocks: &StatBlockEncyclopedia,
-) -> Stat {
-    let mut tokens = f.split(' ').collect::<VecDeque<_>>();
-    _eval_hit(&mut tokens, actor, target, statblocks)
+fn parse_term(pos: usize, token: &str) -> Result<Term, FormulaError> {
+    let unknown = || FormulaError::UnknownToken {
+        pos,
+        token: token.to_string(),
+    };
+    if let Ok(v) = token.parse::<Stat>() {
+        return Ok(Term::Constant(v));
+    }
+    if let Some(stat) = token.strip_prefix('^') {
+        return parse_stat_ref(stat).map(Term::Actor).ok_or_else(unknown);
+    }
+    if let Some(stat) = token.strip_prefix('$') {
+        return parse_stat_ref(stat).map(Term::Target).ok_or_else(unknown);
+    }
+    match token.split_once('.') {
+        Some((owner, "")) => Ok(Term::Base(optional_stat_name(owner))),
+        Some((owner, "AddMod")) => Ok(Term::AddMod(optional_stat_name(owner))),
+        Some((owner, "MultMod")) => Ok(Term::MultMod(optional_stat_name(owner))),
+        Some((owner, attr)) if !owner.is_empty() && !attr.contains('.') => {
+            Ok(Term::ItemAttr(Name::from(owner), Name::from(attr)))
+        }
+        _ => Err(unknown()),
+    }
 }
 
-fn _eval_hit(
-    tokens: &mut VecDeque<&str>,
-    actor: Option<&Character>,
-    target: &Character,
-    statblocks: &StatBlockEncyclopedia,
-) -> Stat {
-    match tokens.pop_front() {
-        Some("+") => _eval_hit(tokens, actor, target, statblocks)
-            .saturating_add(_eval_hit(tokens, actor, target, statblocks)),
-        Some("-") => _eval_hit(tokens, actor, target, statblocks)
-            .saturating_sub(_eval_hit(tokens, actor, target, statblocks)),
-        Some("*") => _eval_hit(tokens, actor, target, statblocks)
-            .saturating_mul(_eval_hit(tokens, actor, target, statblocks)),
-        Some("/") => _eval_hit(tokens, actor, target, statblocks)
-            .saturating_div(_eval_hit(tokens, actor, target, statblocks)),
-        //        Some("^") => _eval_hit(tokens, actor, target, statblocks).saturating_pow(_eval_hit(tokens, actor, target, statblocks)),
-        Some(term) => _eval_term(term, actor, target, statblocks),
-        None => panic!("Ran out of tokens in _eval_hit"),
-    }
-}
-
-fn _eval_term(
-    term: &str,
+fn parse_operand(pos: usize, token: &str) -> Result<Node, FormulaError> {
+    match parse_dice(pos, token)? {
+        Some(dice) => Ok(dice),
+        None => Ok(Node::Term(parse_term(pos, token)?)),
+    }
+}
+
+fn is_prefix_operator(token: &str) -> bool {
+    token == "?" || Op::from_token(token).is_some() || Func::from_name(token).is_some()
+}
+
+// Prefix notation, e.g. "+ 1 / ^Offense 2" or "min 9999 * 2 rand 224 255"
+fn parse_prefix(tokens: &[&str], pos: &mut usize) -> Result<Node, FormulaError> {
+    let token = match tokens.get(*pos) {
+        Some(token) => *token,
+        None => return Err(FormulaError::UnexpectedEnd { pos: *pos }),
+    };
+    *pos += 1;
+    if let Some(op) = Op::from_token(token) {
+        let a = parse_prefix(tokens, pos)?;
+        let b = parse_prefix(tokens, pos)?;
+        return Ok(Node::BinOp(op, Box::new(a), Box::new(b)));
+    }
+    if let Some(func) = Func::from_name(token) {
+        let mut args = Vec::<Node>::new();
+        for _ in 0..func.arity() {
+            args.push(parse_prefix(tokens, pos)?);
+        }
+        return Ok(Node::Call(func, args));
+    }
+    if token == "?" {
+        let cond = parse_prefix(tokens, pos)?;
+        let a = parse_prefix(tokens, pos)?;
+        let b = parse_prefix(tokens, pos)?;
+        return Ok(Node::Cond(Box::new(cond), Box::new(a), Box::new(b)));
+    }
+    parse_operand(*pos - 1, token)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '.' || c == '_'
+}
+
+// Splits an infix formula into tokens. A '-' is part of a name (standing in
+// for a space, as in $Magic-Defense) only when it is followed by a letter.
+fn lex_infix(s: &str) -> Result<Vec<String>, FormulaError> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::<String>::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        if "+-*/(),?:".contains(c) {
+            i += 1;
+        } else if "<>=!".contains(c) {
+            i += if next == Some('=') { 2 } else { 1 };
+        } else if is_word_char(c) || c == '^' || c == '$' {
+            i += 1;
+            while i < chars.len() {
+                let c = chars[i];
+                let next = chars.get(i + 1).copied();
+                if is_word_char(c) || (c == '-' && next.is_some_and(|n| n.is_alphabetic())) {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+        } else {
+            return Err(FormulaError::UnknownToken {
+                pos: tokens.len(),
+                token: c.to_string(),
+            });
+        }
+        tokens.push(chars[start..i].iter().collect());
+    }
+    Ok(tokens)
+}
+
+// Infix notation, e.g. "(^Offense * 2 + ^Strength) / $Defense"
+struct InfixParser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl<'a> InfixParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+    fn next(&mut self) -> Result<&'a str, FormulaError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.as_str())
+            }
+            None => Err(FormulaError::UnexpectedEnd { pos: self.pos }),
+        }
+    }
+    fn expect(&mut self, expected: &str) -> Result<(), FormulaError> {
+        let pos = self.pos;
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(FormulaError::UnexpectedToken {
+                pos,
+                token: token.to_string(),
+            }),
+        }
+    }
+    fn binary(
+        &mut self,
+        ops: &[&str],
+        operand: fn(&mut Self) -> Result<Node, FormulaError>,
+    ) -> Result<Node, FormulaError> {
+        let mut node = operand(self)?;
+        while let Some(token) = self.peek().filter(|t| ops.contains(t)) {
+            self.pos += 1;
+            let rhs = operand(self)?;
+            node = Node::BinOp(
+                Op::from_token(token).unwrap(),
+                Box::new(node),
+                Box::new(rhs),
+            );
+        }
+        Ok(node)
+    }
+    fn ternary(&mut self) -> Result<Node, FormulaError> {
+        let cond = self.comparison()?;
+        if self.peek() != Some("?") {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let a = self.ternary()?;
+        self.expect(":")?;
+        let b = self.ternary()?;
+        Ok(Node::Cond(Box::new(cond), Box::new(a), Box::new(b)))
+    }
+    fn comparison(&mut self) -> Result<Node, FormulaError> {
+        self.binary(&["<", "<=", ">", ">=", "==", "!="], Self::additive)
+    }
+    fn additive(&mut self) -> Result<Node, FormulaError> {
+        self.binary(&["+", "-"], Self::multiplicative)
+    }
+    fn multiplicative(&mut self) -> Result<Node, FormulaError> {
+        self.binary(&["*", "/"], Self::unary)
+    }
+    fn unary(&mut self) -> Result<Node, FormulaError> {
+        if self.peek() == Some("-") {
+            self.pos += 1;
+            // "-5" is the constant, the same as in prefix
+            return Ok(match self.unary()? {
+                Node::Term(Term::Constant(v)) => Node::Term(Term::Constant(v.saturating_neg())),
+                node => Node::Neg(Box::new(node)),
+            });
+        }
+        self.primary()
+    }
+    fn primary(&mut self) -> Result<Node, FormulaError> {
+        let pos = self.pos;
+        let token = self.next()?;
+        if token == "(" {
+            let node = self.ternary()?;
+            self.expect(")")?;
+            return Ok(node);
+        }
+        if let Some(func) = Func::from_name(token) {
+            self.expect("(")?;
+            let mut args = vec![self.ternary()?];
+            while self.peek() == Some(",") {
+                self.pos += 1;
+                args.push(self.ternary()?);
+            }
+            self.expect(")")?;
+            if args.len() != func.arity() {
+                return Err(FormulaError::WrongArgCount {
+                    pos,
+                    expected: func.arity(),
+                });
+            }
+            return Ok(Node::Call(func, args));
+        }
+        if token.starts_with(|c: char| "+-*/(),?:<>=!".contains(c)) {
+            return Err(FormulaError::UnexpectedToken {
+                pos,
+                token: token.to_string(),
+            });
+        }
+        parse_operand(pos, token)
+    }
+}
+
+// What a Formula's terms refer to while it is being evaluated
+trait Env {
+    fn resolve(&self, term: &Term) -> Stat;
+    fn roll(&mut self, lo: Stat, hi: Stat) -> Stat;
+}
+
+struct HitEnv<'a, R: Rng> {
+    actor: Option<&'a Character>,
+    target: &'a Character,
+    statblocks: &'a StatBlockEncyclopedia,
+    rng: &'a mut R,
+}
+
+impl<'a, R: Rng> Env for HitEnv<'a, R> {
+    fn resolve(&self, term: &Term) -> Stat {
+        match term {
+            Term::Constant(v) => *v,
+            // Nothing to refer to, e.g. for a Condition ticking on its own
+            Term::Actor(stat) => self
+                .actor
+                .map_or(0, |actor| stat.resolve(actor, self.statblocks)),
+            Term::Target(stat) => stat.resolve(self.target, self.statblocks),
+            // HitFormula turns these away when it's parsed
+            _ => 0,
+        }
+    }
+    fn roll(&mut self, lo: Stat, hi: Stat) -> Stat {
+        self.rng.gen_range(lo.min(hi)..=hi.max(lo))
+    }
+}
+
+struct StatEnv<'a> {
+    stat_name: &'a Name,
+    c: &'a Character,
+}
+
+impl<'a> Env for StatEnv<'a> {
+    fn resolve(&self, term: &Term) -> Stat {
+        let or_this = |name: &Option<Name>| name.clone().unwrap_or_else(|| self.stat_name.clone());
+        let c = self.c;
+        match term {
+            Term::Constant(v) => *v,
+            // Base stats the Character doesn't have count as 0
+            Term::Base(name) => c.get_base_stat(or_this(name)).copied().unwrap_or(0),
+            Term::AddMod(name) => c.sum_add_mods(or_this(name)),
+            Term::MultMod(name) => c.sum_mult_mods(or_this(name)),
+            // As do slots the Character doesn't have
+            Term::ItemAttr(slot, attr) => c.get_item_attr(slot.clone(), attr.clone()).unwrap_or(0),
+            // StatFormula turns these away when it's parsed
+            _ => 0,
+        }
+    }
+    fn roll(&mut self, _lo: Stat, _hi: Stat) -> Stat {
+        // StatFormula turns rolls away when it's parsed
+        0
+    }
+}
+
+fn eval<E: Env>(node: &Node, env: &mut E) -> Stat {
+    match node {
+        Node::Term(term) => env.resolve(term),
+        Node::Dice(n, sides) => (0..*n)
+            .map(|_| env.roll(1, *sides as Stat))
+            .fold(0, Stat::saturating_add),
+        Node::Neg(a) => eval(a, env).saturating_neg(),
+        Node::BinOp(op, a, b) => {
+            let a = eval(a, env);
+            op.apply(a, eval(b, env))
+        }
+        Node::Call(func, args) => {
+            let args = args.iter().map(|a| eval(a, env)).collect::<Vec<_>>();
+            match func {
+                Func::Min => args[0].min(args[1]),
+                Func::Max => args[0].max(args[1]),
+                Func::Clamp => args[0].max(args[1]).min(args[2]),
+                Func::Pow => match u32::try_from(args[1]) {
+                    Ok(exp) => args[0].saturating_pow(exp),
+                    Err(_) => 0,
+                },
+                Func::Rand => env.roll(args[0], args[1]),
+            }
+        }
+        Node::Cond(cond, a, b) => {
+            if eval(cond, env) != 0 {
+                eval(a, env)
+            } else {
+                eval(b, env)
+            }
+        }
+    }
+}
+
+/// A hit or derived stat formula, written either in prefix notation, e.g.
+/// "+ 1 / ^Offense 2", or in infix notation, e.g. "1 + ^Offense / 2".
+/// Formulas are read as prefix if they can be, and as infix otherwise, so
+/// "- 5 3" is 2 but "-$HP.max / 4" is infix. Formulas that could be read
+/// either way with different results are turned away.
+/// Formulas are parsed once, when they are read from JSON, and can then be
+/// evaluated any number of times as a HitFormula or a StatFormula.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Formula {
+    src: String,
+    root: Node,
+}
+
+impl Formula {
+    pub fn as_str(&self) -> &str {
+        &self.src
+    }
+    fn terms(&self) -> Vec<&Term> {
+        let mut nodes = Vec::<&Node>::new();
+        self.root.walk(&mut nodes);
+        nodes
+            .into_iter()
+            .filter_map(|node| match node {
+                Node::Term(term) => Some(term),
+                _ => None,
+            })
+            .collect()
+    }
+    // Turns the Formula away if any of its Nodes aren't allowed in its kind
+    fn check(
+        self,
+        kind: &'static str,
+        allowed: fn(&Node) -> bool,
+    ) -> Result<Formula, FormulaError> {
+        let mut nodes = Vec::<&Node>::new();
+        self.root.walk(&mut nodes);
+        match nodes.into_iter().find(|n| !allowed(n)) {
+            Some(node) => Err(FormulaError::NotAllowed {
+                token: node.token(),
+                kind,
+            }),
+            None => Ok(self),
+        }
+    }
+    fn parse_prefix(s: &str) -> Result<Node, FormulaError> {
+        let tokens = s.split_whitespace().collect::<Vec<_>>();
+        let mut pos = 0;
+        let root = parse_prefix(&tokens, &mut pos)?;
+        if pos < tokens.len() {
+            return Err(FormulaError::TrailingTokens { pos });
+        }
+        Ok(root)
+    }
+    fn parse_infix(s: &str) -> Result<Node, FormulaError> {
+        let tokens = lex_infix(s)?;
+        let mut parser = InfixParser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let root = parser.ternary()?;
+        if parser.pos < tokens.len() {
+            return Err(FormulaError::TrailingTokens { pos: parser.pos });
+        }
+        Ok(root)
+    }
+}
+
+impl FromStr for Formula {
+    type Err = FormulaError;
+    fn from_str(s: &str) -> Result<Formula, FormulaError> {
+        let first = s.split_whitespace().next().ok_or(FormulaError::Empty)?;
+        let root = match (Formula::parse_prefix(s), Formula::parse_infix(s)) {
+            (Ok(prefix), Ok(infix)) if prefix != infix => return Err(FormulaError::Ambiguous),
+            (Ok(root), _) | (_, Ok(root)) => root,
+            // Neither works, so go by what it looks like
+            (Err(e), _) if is_prefix_operator(first) => return Err(e),
+            (_, Err(e)) => return Err(e),
+        };
+        Ok(Formula {
+            src: s.to_string(),
+            root,
+        })
+    }
+}
+
+impl TryFrom<String> for Formula {
+    type Error = FormulaError;
+    fn try_from(s: String) -> Result<Formula, FormulaError> {
+        s.parse()
+    }
+}
+
+impl From<Formula> for String {
+    fn from(f: Formula) -> String {
+        f.src
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+/// A Formula for how an Effect lands on a target, e.g. "* ^Offense rand 224 255".
+/// It may refer to the actor's and target's stats and pools and roll dice,
+/// but not to base stats, mods or equipment.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct HitFormula(Formula);
+
+impl HitFormula {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+    pub fn eval_hit<R: Rng>(
+        &self,
+        actor: Option<&Character>,
+        target: &Character,
+        statblocks: &StatBlockEncyclopedia,
+        rng: &mut R,
+    ) -> Stat {
+        let mut env = HitEnv {
+            actor,
+            target,
+            statblocks,
+            rng,
+        };
+        eval(&self.0.root, &mut env)
+    }
+    /// Checks that every ^actor and $target stat or pool is declared by a StatBlock
+    pub fn check_stats(&self, statblocks: &StatBlockEncyclopedia) -> Result<(), FormulaError> {
+        for term in self.0.terms() {
+            let declared = match term {
+                Term::Actor(StatRef::Stat(name)) | Term::Target(StatRef::Stat(name)) => {
+                    statblocks.has_stat(name)
+                }
+                Term::Actor(pool) | Term::Target(pool) => statblocks.has_pool(pool.name()),
+                _ => true,
+            };
+            if !declared {
+                return Err(FormulaError::UnknownStat {
+                    token: term.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HitFormula {
+    type Err = FormulaError;
+    fn from_str(s: &str) -> Result<HitFormula, FormulaError> {
+        let allowed = |node: &Node| match node {
+            Node::Term(term) => {
+                matches!(term, Term::Constant(_) | Term::Actor(_) | Term::Target(_))
+            }
+            _ => true,
+        };
+        Ok(HitFormula(s.parse::<Formula>()?.check("hit", allowed)?))
+    }
+}
+
+impl TryFrom<String> for HitFormula {
+    type Error = FormulaError;
+    fn try_from(s: String) -> Result<HitFormula, FormulaError> {
+        s.parse()
+    }
+}
+
+impl From<HitFormula> for String {
+    fn from(f: HitFormula) -> String {
+        f.0.into()
+    }
+}
+
+impl fmt::Display for HitFormula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A Formula deriving a stat from a Character's base stats, mods and
+/// equipment, e.g. "+ .AddMod / * .MultMod + Weapon.Power . 100".
+/// It can't refer to ^actor or $target stats or roll dice.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct StatFormula(Formula);
+
+impl StatFormula {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+    pub fn eval_stat(&self, stat_name: &Name, c: &Character) -> Stat {
+        eval(&self.0.root, &mut StatEnv { stat_name, c })
+    }
+    /// Checks that every base stat and mod it refers to, including those of
+    /// the stat being derived, is declared by the StatBlock
+    pub fn check_stats(&self, stat_name: &Name, statblock: &StatBlock) -> Result<(), FormulaError> {
+        for term in self.0.terms() {
+            let declared = match term {
+                Term::Base(name) => statblock.has_base_stat(name.as_ref().unwrap_or(stat_name)),
+                Term::AddMod(name) | Term::MultMod(name) => {
+                    statblock.has_stat(name.as_ref().unwrap_or(stat_name))
+                }
+                _ => true,
+            };
+            if !declared {
+                return Err(FormulaError::UnknownStat {
+                    token: term.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for StatFormula {
+    type Err = FormulaError;
+    fn from_str(s: &str) -> Result<StatFormula, FormulaError> {
+        let allowed = |node: &Node| match node {
+            Node::Term(term) => !matches!(term, Term::Actor(_) | Term::Target(_)),
+            Node::Dice(..) | Node::Call(Func::Rand, _) => false,
+            _ => true,
+        };
+        Ok(StatFormula(
+            s.parse::<Formula>()?.check("derived stat", allowed)?,
+        ))
+    }
+}
+
+impl TryFrom<String> for StatFormula {
+    type Error = FormulaError;
+    fn try_from(s: String) -> Result<StatFormula, FormulaError> {
+        s.parse()
+    }
+}
+
+impl From<StatFormula> for String {
+    fn from(f: StatFormula) -> String {
+        f.0.into()
+    }
+}
+
+impl fmt::Display for StatFormula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn eval_stat(stat_name: Name, s: &DerivedStat, c: &Character) -> Stat {
+    s.eval_stat(&stat_name, c)
+}
+
+pub fn eval_hit<R: Rng>(
+    f: &HitFormula,
     actor: Option<&Character>,
     target: &Character,
     statblocks: &StatBlockEncyclopedia,
+    rng: &mut R,
 ) -> Stat {
-    if let Ok(v) = term.parse::<Stat>() {
-        return v;
-    }
-    let actor = actor.unwrap();
-    // todo generalizations
-    match term {
-        "^Level" => actor.get_stat_val(String::from("Level"), 1, statblocks),
-        "^Offense" => actor.get_stat_val(String::from("Offense"), 0, statblocks),
-        "^Strength" => actor.get_stat_val(String::from("Strength"), 0, statblocks),
-        "^Magic" => actor.get_stat_val(String::from("Magic"), 0, statblocks),
-        "$Offense" => target.get_stat_val(String::from("Offense"), 0, statblocks),
-        "$Defense" => target.get_stat_val(String::from("Defense"), 0, statblocks),
-        "$Magic-Defense" => target.get_stat_val(String::from("Magic Defense"), 0, statblocks),
-        t => panic!("Could not understand token {}.", t),
-    }
+    f.eval_hit(actor, target, statblocks, rng)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::item::Item;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
-    fn eval_stat_term_test() {
-        let term = "1";
-        let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
-        assert_eq!(_eval_stat_term(term, &c, &String::new()), 1);
+    fn parse_term_test() {
+        assert_eq!(parse_term(0, "1"), Ok(Term::Constant(1)));
+        assert_eq!(parse_term(0, "-1"), Ok(Term::Constant(-1)));
+        assert_eq!(parse_term(0, "."), Ok(Term::Base(None)));
+        assert_eq!(
+            parse_term(0, "$Magic-Defense"),
+            Ok(Term::Target(StatRef::Stat(String::from("Magic Defense"))))
+        );
+        assert_eq!(
+            parse_term(0, "^HP.max"),
+            Ok(Term::Actor(StatRef::PoolMax(String::from("HP"))))
+        );
+        assert_eq!(
+            parse_term(0, "Weapon.Power"),
+            Ok(Term::ItemAttr(
+                String::from("Weapon"),
+                String::from("Power")
+            ))
+        );
     }
     #[test]
     fn eval_hit_test() {
-        let f = Formula::from("+ 1 - ^Offense / $Offense 2");
+        let f = "+ 1 - ^Offense / $Offense 2".parse::<HitFormula>().unwrap();
         let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
         let statblocks = StatBlockEncyclopedia::new("data/stats.json");
         let offense: Stat = c.get_stat_val(String::from("Offense"), 0, &statblocks);
         let expected: Stat = 1 + offense - offense / 2;
-        let evaluated = eval_hit(&f, Some(&c), &c, &statblocks);
+        let evaluated = eval_hit(&f, Some(&c), &c, &statblocks, &mut rand::thread_rng());
         assert_eq!(evaluated, expected);
+        // Evaluating again gives the same result
+        assert_eq!(
+            eval_hit(&f, Some(&c), &c, &statblocks, &mut rand::thread_rng()),
+            expected
+        );
     }
     #[test]
     fn eval_stat_test() {
-        let ds = DerivedStat::from("+ .AddMod * .MultMod + Weapon.power .");
-        let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
-        //let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let ds = "+ .AddMod / * .MultMod + Weapon.power . 100"
+            .parse::<DerivedStat>()
+            .unwrap();
+        let mut c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
         let offense: Stat = *c.get_base_stat(String::from("Offense")).unwrap();
-        let expected: Stat = offense + 10; // todo, see Character::get_item_attr
+        // Nothing equipped yet
+        assert_eq!(eval_stat(Name::from("Offense"), &ds, &c), offense);
+        let spear =
+            Item::from_json(r#"{"id": 0, "name": "Spear", "power": 6, "slots": ["Weapon"]}"#);
+        c.equip_to_slot(spear, String::from("Weapon")).unwrap();
+        let expected: Stat = offense + 6;
         let evaluated = eval_stat(Name::from("Offense"), &ds, &c);
         assert_eq!(evaluated, expected);
     }
     #[test]
-    #[should_panic]
+    fn serde_test() {
+        let f: Formula = serde_json::from_str(r#""+ 1 ^Level""#).unwrap();
+        assert_eq!(f.as_str(), "+ 1 ^Level");
+        assert_eq!(serde_json::to_string(&f).unwrap(), r#""+ 1 ^Level""#);
+        assert!(serde_json::from_str::<Formula>(r#""+ 1""#).is_err());
+    }
+    #[test]
+    fn empty_formula_test() {
+        assert_eq!("".parse::<HitFormula>(), Err(FormulaError::Empty));
+    }
+    #[test]
     fn incomplete_hit_formula_test() {
-        let f = Formula::from("+ 1");
+        assert_eq!(
+            "+ 1".parse::<HitFormula>(),
+            Err(FormulaError::UnexpectedEnd { pos: 2 })
+        );
+    }
+    #[test]
+    fn bad_hit_formula_test() {
+        assert_eq!(
+            "+ 1 $HP.min".parse::<HitFormula>(),
+            Err(FormulaError::UnknownToken {
+                pos: 2,
+                token: String::from("$HP.min")
+            })
+        );
+        assert!("+ 1 ^".parse::<HitFormula>().is_err());
+        assert!("+ 1 $.max".parse::<HitFormula>().is_err());
+    }
+    #[test]
+    fn generic_stat_ref_test() {
+        let c = Character::from_json(
+            r#"{"id": 0, "name": "Test", "base_stats": {"Magic Evasion": 7},
+            "pools": {"HP": {"name": "HP", "current": 3, "maximum": 12}}}"#,
+        );
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let f = "+ ^Magic-Evasion $Evasion".parse::<HitFormula>().unwrap();
+        assert_eq!(f.check_stats(&statblocks), Ok(()));
+        assert_eq!(
+            f.eval_hit(Some(&c), &c, &statblocks, &mut rand::thread_rng()),
+            7
+        );
+        let f = "- $HP.max ^HP.current".parse::<HitFormula>().unwrap();
+        assert_eq!(
+            f.eval_hit(Some(&c), &c, &statblocks, &mut rand::thread_rng()),
+            9
+        );
+        let f = "* ^Magic-Evasion $MP.max".parse::<HitFormula>().unwrap();
+        assert_eq!(
+            f.eval_hit(Some(&c), &c, &statblocks, &mut rand::thread_rng()),
+            0
+        );
+    }
+    #[test]
+    fn unknown_stat_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let check = |s: &str| s.parse::<HitFormula>().unwrap().check_stats(&statblocks);
+        assert_eq!(check("- $HP.max ^MP.current"), Ok(()));
+        assert_eq!(
+            check("+ ^Offense $Moxie"),
+            Err(FormulaError::UnknownStat {
+                token: String::from("$Moxie")
+            })
+        );
+        assert_eq!(
+            check("^Mana.max"),
+            Err(FormulaError::UnknownStat {
+                token: String::from("^Mana.max")
+            })
+        );
+        let pc = statblocks.get(&0).unwrap();
+        let check = |s: &str| {
+            s.parse::<DerivedStat>()
+                .unwrap()
+                .check_stats(&String::from("Offense"), pc)
+        };
+        assert_eq!(check("+ .AddMod / * .MultMod + Weapon.Power . 100"), Ok(()));
+        assert_eq!(check("+ . Magic-Defense."), Ok(()));
+        assert_eq!(
+            check("+ . Moxie."),
+            Err(FormulaError::UnknownStat {
+                token: String::from("Moxie.")
+            })
+        );
+        assert!(check("* . Luck.MultMod").is_err());
+        // The stat being derived needs a base stat of its own for "."
+        assert!(". + 1"
+            .parse::<DerivedStat>()
+            .unwrap()
+            .check_stats(&String::from("Moxie"), pc)
+            .is_err());
+    }
+    #[test]
+    fn infix_test() {
         let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
         let statblocks = StatBlockEncyclopedia::new("data/stats.json");
-        eval_hit(&f, Some(&c), &c, &statblocks);
+        let mut rng = StdRng::seed_from_u64(0);
+        let prefix = "/ + * ^Offense 2 ^Strength $Magic"
+            .parse::<HitFormula>()
+            .unwrap();
+        let infix = "(^Offense * 2 + ^Strength) / $Magic"
+            .parse::<HitFormula>()
+            .unwrap();
+        assert_eq!(
+            prefix.eval_hit(Some(&c), &c, &statblocks, &mut rng),
+            infix.eval_hit(Some(&c), &c, &statblocks, &mut rng)
+        );
+        let f = "1 + 2 * 3 - -4 / 2".parse::<HitFormula>().unwrap();
+        assert_eq!(f.eval_hit(None, &c, &statblocks, &mut rng), 9);
+        let ds = ".AddMod + .MultMod * (Weapon.Power + .) / 100"
+            .parse::<DerivedStat>()
+            .unwrap();
+        let offense: Stat = *c.get_base_stat(String::from("Offense")).unwrap();
+        assert_eq!(eval_stat(Name::from("Offense"), &ds, &c), offense);
     }
     #[test]
-    #[should_panic]
-    fn bad_hit_formula_test() {
-        let f = Formula::from("+ 1 $Moxie");
+    fn prefix_or_infix_test() {
+        let c = Character::from_json(
+            r#"{"id": 0, "name": "Test", "pools": {"HP": {"name": "HP", "current": 3, "maximum": 12}}}"#,
+        );
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut eval = |s: &str| {
+            s.parse::<HitFormula>()
+                .unwrap()
+                .eval_hit(None, &c, &statblocks, &mut rng)
+        };
+        assert_eq!(eval("- 5 3"), 2);
+        assert_eq!(eval("-$HP.max / 4"), -3);
+        assert_eq!(eval("- $HP.max / 4"), -3);
+        assert_eq!(eval("-5 - 3"), -8);
+        assert_eq!(eval("- -5 3"), -8);
+        // Both readings agree
+        assert_eq!(eval("7"), 7);
+        assert_eq!(eval("-7"), -7);
+        assert_eq!("- 1 -2".parse::<Formula>(), Err(FormulaError::Ambiguous));
+        assert_eq!(
+            "- 5 3".parse::<Formula>().unwrap().root,
+            Node::BinOp(
+                Op::Sub,
+                Box::new(Node::Term(Term::Constant(5))),
+                Box::new(Node::Term(Term::Constant(3)))
+            )
+        );
+    }
+    #[test]
+    fn lex_infix_test() {
+        assert_eq!(
+            lex_infix("$Magic-Defense-2*(^HP.max>=.)").unwrap(),
+            vec![
+                "$Magic-Defense",
+                "-",
+                "2",
+                "*",
+                "(",
+                "^HP.max",
+                ">=",
+                ".",
+                ")"
+            ]
+        );
+    }
+    #[test]
+    fn divide_by_zero_test() {
+        let c = Character::from_json(
+            r#"{"id": 0, "name": "Test", "base_stats": {"Offense": 10, "Defense": 0}}"#,
+        );
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut rng = StdRng::seed_from_u64(0);
+        let prefix = "/ ^Offense $Defense".parse::<HitFormula>().unwrap();
+        assert_eq!(prefix.eval_hit(Some(&c), &c, &statblocks, &mut rng), 0);
+        let infix = "^Offense / $Defense + 1".parse::<HitFormula>().unwrap();
+        assert_eq!(infix.eval_hit(Some(&c), &c, &statblocks, &mut rng), 1);
+        let ds = "+ 1 / Offense. Defense.".parse::<DerivedStat>().unwrap();
+        assert_eq!(eval_stat(Name::from("Offense"), &ds, &c), 1);
+        let ds = "Offense. / (Defense. * 2)".parse::<DerivedStat>().unwrap();
+        assert_eq!(eval_stat(Name::from("Offense"), &ds, &c), 0);
+        assert_eq!(Op::Div.apply(Stat::MIN, -1), Stat::MAX);
+    }
+    #[test]
+    fn functions_test() {
         let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
         let statblocks = StatBlockEncyclopedia::new("data/stats.json");
-        eval_hit(&f, Some(&c), &c, &statblocks);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut eval = |s: &str| {
+            s.parse::<HitFormula>()
+                .unwrap()
+                .eval_hit(None, &c, &statblocks, &mut rng)
+        };
+        assert_eq!(eval("min(3, 4)"), 3);
+        assert_eq!(eval("max 3 4"), 4);
+        assert_eq!(eval("clamp(12, 0, 9) + clamp(-1, 0, 9)"), 9);
+        assert_eq!(eval("pow(2, 10)"), 1024);
+        assert_eq!(eval("pow 2 -1"), 0);
+        assert_eq!(eval("3 < 4"), 1);
+        assert_eq!(eval("3 >= 4"), 0);
+        assert_eq!(eval("1 == 1 ? 2 : 3"), 2);
+        assert_eq!(eval("? != 1 1 2 3"), 3);
+        assert_eq!(eval("0 ? 1 : 0 ? 2 : 3"), 3);
     }
     #[test]
-    #[should_panic]
-    fn incomplete_stat_formula_test() {
-        let ds = DerivedStat::from("+ 1");
+    fn random_test() {
         let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
-        eval_stat(Name::new(), &ds, &c);
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let f = "rand(224, 255) + 2d6".parse::<HitFormula>().unwrap();
+        let mut rng = StdRng::seed_from_u64(1234);
+        let rolls = (0..100)
+            .map(|_| f.eval_hit(None, &c, &statblocks, &mut rng))
+            .collect::<Vec<_>>();
+        assert!(rolls.iter().all(|v| (226..=267).contains(v)));
+        // The same seed gives the same rolls
+        let mut rng = StdRng::seed_from_u64(1234);
+        let replayed = (0..100)
+            .map(|_| f.eval_hit(None, &c, &statblocks, &mut rng))
+            .collect::<Vec<_>>();
+        assert_eq!(rolls, replayed);
+        let f = "* ^Offense rand 224 255".parse::<HitFormula>().unwrap();
+        assert!(f.eval_hit(Some(&c), &c, &statblocks, &mut rng) > 0);
+    }
+    #[test]
+    fn random_stat_formula_test() {
+        assert_eq!(
+            "+ . 1d6".parse::<DerivedStat>(),
+            Err(FormulaError::NotAllowed {
+                token: String::from("1d6"),
+                kind: "derived stat"
+            })
+        );
+        assert!(". + rand(1, 6)".parse::<DerivedStat>().is_err());
+    }
+    #[test]
+    fn wrong_kind_test() {
+        assert_eq!(
+            "+ . $Speed".parse::<DerivedStat>(),
+            Err(FormulaError::NotAllowed {
+                token: String::from("$Speed"),
+                kind: "derived stat"
+            })
+        );
+        assert_eq!(
+            "+ ^Offense Weapon.Power".parse::<HitFormula>(),
+            Err(FormulaError::NotAllowed {
+                token: String::from("Weapon.Power"),
+                kind: "hit"
+            })
+        );
+        assert!("* 2 Magic-Defense.AddMod".parse::<HitFormula>().is_err());
+        assert!("+ 1 .".parse::<HitFormula>().is_err());
+        assert!(serde_json::from_str::<HitFormula>(r#""+ .MultMod 1""#).is_err());
+        // Plain Formulas take any term
+        assert!("+ ^Offense Weapon.Power".parse::<Formula>().is_ok());
+    }
+    #[test]
+    fn bad_infix_formula_test() {
+        assert_eq!(
+            "(1 + 2".parse::<HitFormula>(),
+            Err(FormulaError::UnexpectedEnd { pos: 4 })
+        );
+        assert_eq!(
+            "1 + 2)".parse::<HitFormula>(),
+            Err(FormulaError::TrailingTokens { pos: 3 })
+        );
+        assert_eq!(
+            "1 + * 2".parse::<HitFormula>(),
+            Err(FormulaError::UnexpectedToken {
+                pos: 2,
+                token: String::from("*")
+            })
+        );
+        assert_eq!(
+            "clamp(1, 2)".parse::<HitFormula>(),
+            Err(FormulaError::WrongArgCount {
+                pos: 0,
+                expected: 3
+            })
+        );
+        assert_eq!(
+            "1 + 2 % 3".parse::<HitFormula>(),
+            Err(FormulaError::UnknownToken {
+                pos: 3,
+                token: String::from("%")
+            })
+        );
+        assert_eq!(
+            "1d0".parse::<HitFormula>(),
+            Err(FormulaError::BadDice {
+                pos: 0,
+                token: String::from("1d0")
+            })
+        );
+        assert_eq!(
+            "+ 1 100000000d6".parse::<HitFormula>(),
+            Err(FormulaError::BadDice {
+                pos: 2,
+                token: String::from("100000000d6")
+            })
+        );
+        assert!("1000d6 + 1".parse::<HitFormula>().is_ok());
+        assert!("1001d6 + 1".parse::<HitFormula>().is_err());
+        assert!("1 ? 2".parse::<HitFormula>().is_err());
+    }
+    #[test]
+    fn trailing_tokens_test() {
+        assert_eq!(
+            "+ 1 2 3".parse::<HitFormula>(),
+            Err(FormulaError::TrailingTokens { pos: 3 })
+        );
+    }
+    #[test]
+    fn incomplete_stat_formula_test() {
+        assert_eq!(
+            "+ 1".parse::<DerivedStat>(),
+            Err(FormulaError::UnexpectedEnd { pos: 2 })
+        );
+    }
+    #[test]
+    fn bad_stat_term_test() {
+        assert_eq!(
+            "+ 1 Moxie".parse::<DerivedStat>(),
+            Err(FormulaError::UnknownToken {
+                pos: 2,
+                token: String::from("Moxie")
+            })
+        );
     }
     #[test]
-    #[should_panic]
     fn bad_stat_formula_test() {
-        let ds = DerivedStat::from("+ 1 Moxie.");
+        let ds = "+ 1 Moxie.".parse::<DerivedStat>().unwrap();
         let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
-        eval_stat(Name::new(), &ds, &c);
+        assert_eq!(eval_stat(Name::new(), &ds, &c), 1);
     }
     #[test]
-    #[should_panic]
     fn bad_attr_in_stat_formula_test() {
-        let ds = DerivedStat::from("+ 1 Moxie.Blarg");
+        let ds = "+ 1 Moxie.Blarg".parse::<DerivedStat>().unwrap();
         let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
-        eval_stat(Name::new(), &ds, &c);
+        assert_eq!(eval_stat(Name::new(), &ds, &c), 1);
+    }
+    #[test]
+    fn no_actor_test() {
+        let c = Character::from_json(r#"{"id": 0, "name": "Test"}"#);
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let f = "+ 1 ^Offense".parse::<HitFormula>().unwrap();
+        assert_eq!(
+            f.eval_hit(None, &c, &statblocks, &mut rand::thread_rng()),
+            1
+        );
     }
 }
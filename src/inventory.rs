@@ -0,0 +1,296 @@
+use core::slice::Iter;
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+use crate::item::Item;
+
+pub type Quantity = u16;
+pub type Gold = u32;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Stack {
+    pub item: Item,
+    pub quantity: Quantity,
+}
+
+impl Stack {
+    fn room(&self) -> Quantity {
+        self.item.max_stack.saturating_sub(self.quantity)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InventoryError {
+    Full,
+    NotEnough { item: Id, have: Quantity },
+    KeyItem(Name),
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InventoryError::Full => write!(f, "the inventory is full"),
+            InventoryError::NotEnough { item, have } => {
+                write!(f, "only {have} of item {item} in the inventory")
+            }
+            InventoryError::KeyItem(name) => write!(f, "{name} is a key item"),
+        }
+    }
+}
+
+impl Error for InventoryError {}
+
+/// Items held in Stacks of up to Item::max_stack, with an optional limit
+/// on the number of Stacks.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct Inventory {
+    stacks: Vec<Stack>,
+    #[serde(default)]
+    capacity: Option<usize>,
+    #[serde(default)]
+    gold: Gold,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+    pub fn with_capacity(capacity: usize) -> Inventory {
+        Inventory {
+            stacks: Vec::<Stack>::new(),
+            capacity: Some(capacity),
+            gold: 0,
+        }
+    }
+    pub fn get_gold(&self) -> Gold {
+        self.gold
+    }
+    pub fn add_gold(&mut self, gold: Gold) {
+        self.gold = self.gold.saturating_add(gold);
+    }
+    /// Returns false, spending nothing, if there isn't enough gold
+    pub fn spend_gold(&mut self, gold: Gold) -> bool {
+        if gold > self.gold {
+            return false;
+        }
+        self.gold -= gold;
+        true
+    }
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.stacks.len()
+    }
+    pub fn iter(&self) -> Iter<'_, Stack> {
+        self.stacks.iter()
+    }
+    pub fn get(&self, id: Id) -> Option<&Item> {
+        self.stacks
+            .iter()
+            .find(|s| s.item.whoami().0 == id)
+            .map(|s| &s.item)
+    }
+    pub fn count(&self, id: Id) -> Quantity {
+        self.stacks
+            .iter()
+            .filter(|s| s.item.whoami().0 == id)
+            .fold(0, |n, s| n.saturating_add(s.quantity))
+    }
+    fn stacks_needed(&self, item: &Item, quantity: Quantity) -> usize {
+        let room = self
+            .stacks
+            .iter()
+            .filter(|s| s.item.whoami().0 == item.whoami().0)
+            .fold(0, |n: Quantity, s| n.saturating_add(s.room()));
+        let max_stack = item.max_stack.max(1) as usize;
+        (quantity.saturating_sub(room) as usize).div_ceil(max_stack)
+    }
+    pub fn has_room_for(&self, item: &Item, quantity: Quantity) -> bool {
+        match self.capacity {
+            Some(capacity) => self.stacks.len() + self.stacks_needed(item, quantity) <= capacity,
+            None => true,
+        }
+    }
+    /// Tops up existing Stacks of the Item before starting new ones.
+    /// Nothing is added if there is not room for all of them.
+    pub fn add(&mut self, item: Item, quantity: Quantity) -> Result<(), InventoryError> {
+        if !self.has_room_for(&item, quantity) {
+            return Err(InventoryError::Full);
+        }
+        let id = item.whoami().0;
+        let mut left = quantity;
+        for stack in self.stacks.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            if stack.item.whoami().0 == id {
+                let n = left.min(stack.room());
+                stack.quantity += n;
+                left -= n;
+            }
+        }
+        let max_stack = item.max_stack.max(1);
+        while left > 0 {
+            let n = left.min(max_stack);
+            self.stacks.push(Stack {
+                item: item.clone(),
+                quantity: n,
+            });
+            left -= n;
+        }
+        Ok(())
+    }
+    /// Takes Items from the last Stacks first, handing back one of them.
+    pub fn remove(&mut self, id: Id, quantity: Quantity) -> Result<Item, InventoryError> {
+        let have = self.count(id);
+        if have < quantity || have == 0 {
+            return Err(InventoryError::NotEnough { item: id, have });
+        }
+        let item = self.get(id).unwrap().clone();
+        let mut left = quantity;
+        for stack in self.stacks.iter_mut().rev() {
+            if stack.item.whoami().0 == id {
+                let n = left.min(stack.quantity);
+                stack.quantity -= n;
+                left -= n;
+            }
+        }
+        self.stacks.retain(|s| s.quantity > 0);
+        Ok(item)
+    }
+    pub fn take(&mut self, id: Id) -> Option<Item> {
+        self.remove(id, 1).ok()
+    }
+    /// Like remove, but key items can't be thrown away
+    pub fn discard(&mut self, id: Id, quantity: Quantity) -> Result<(), InventoryError> {
+        if let Some(item) = self.get(id) {
+            if item.key {
+                return Err(InventoryError::KeyItem(item.copy_name()));
+            }
+        }
+        self.remove(id, quantity).map(|_| ())
+    }
+    pub fn transfer(
+        &mut self,
+        to: &mut Inventory,
+        id: Id,
+        quantity: Quantity,
+    ) -> Result<(), InventoryError> {
+        let have = self.count(id);
+        if have < quantity || have == 0 {
+            return Err(InventoryError::NotEnough { item: id, have });
+        }
+        if !to.has_room_for(self.get(id).unwrap(), quantity) {
+            return Err(InventoryError::Full);
+        }
+        let item = self.remove(id, quantity)?;
+        to.add(item, quantity)
+    }
+    /// Orders the Stacks by Item id, with key items last
+    pub fn sort(&mut self) {
+        self.stacks.sort_by_key(|s| (s.item.key, s.item.whoami().0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn potion() -> Item {
+        Item::from_json(r#"{"id": 0, "name": "Potion", "max_stack": 3}"#)
+    }
+    fn airship() -> Item {
+        Item::from_json(r#"{"id": 9, "name": "Airship Key", "key": true}"#)
+    }
+
+    #[test]
+    fn add_remove_test() {
+        let mut inv = Inventory::new();
+        assert!(inv.is_empty());
+        inv.add(potion(), 4).unwrap();
+        assert_eq!(inv.len(), 2);
+        assert_eq!(inv.count(0), 4);
+        inv.add(potion(), 2).unwrap();
+        assert_eq!(inv.len(), 2);
+        assert_eq!(inv.count(0), 6);
+        assert_eq!(inv.remove(0, 4).unwrap().whoami(), (0, "Potion"));
+        assert_eq!(inv.len(), 1);
+        assert_eq!(inv.count(0), 2);
+        assert_eq!(
+            inv.remove(0, 3),
+            Err(InventoryError::NotEnough { item: 0, have: 2 })
+        );
+        assert!(inv.take(0).is_some());
+        assert!(inv.take(0).is_some());
+        assert!(inv.take(0).is_none());
+        assert!(inv.is_empty());
+    }
+    #[test]
+    fn capacity_test() {
+        let mut inv = Inventory::with_capacity(2);
+        assert_eq!(inv.add(potion(), 7), Err(InventoryError::Full));
+        assert!(inv.is_empty());
+        inv.add(potion(), 5).unwrap();
+        assert!(inv.has_room_for(&potion(), 1));
+        assert!(!inv.has_room_for(&airship(), 1));
+        assert_eq!(inv.add(airship(), 1), Err(InventoryError::Full));
+    }
+    #[test]
+    fn key_item_test() {
+        let mut inv = Inventory::new();
+        inv.add(airship(), 1).unwrap();
+        assert_eq!(
+            inv.discard(9, 1),
+            Err(InventoryError::KeyItem(String::from("Airship Key")))
+        );
+        assert_eq!(inv.count(9), 1);
+        inv.add(potion(), 1).unwrap();
+        assert_eq!(inv.discard(0, 1), Ok(()));
+    }
+    #[test]
+    fn transfer_test() {
+        let mut from = Inventory::new();
+        let mut to = Inventory::with_capacity(1);
+        from.add(potion(), 5).unwrap();
+        assert_eq!(from.transfer(&mut to, 0, 4), Err(InventoryError::Full));
+        assert_eq!(from.count(0), 5);
+        from.transfer(&mut to, 0, 3).unwrap();
+        assert_eq!(from.count(0), 2);
+        assert_eq!(to.count(0), 3);
+    }
+    #[test]
+    fn sort_test() {
+        let mut inv = Inventory::new();
+        inv.add(airship(), 1).unwrap();
+        inv.add(potion(), 2).unwrap();
+        inv.add(Item::from_json(r#"{"id": 1, "name": "Elixer"}"#), 1)
+            .unwrap();
+        inv.add(potion(), 3).unwrap();
+        inv.sort();
+        let order: Vec<(Id, Quantity)> = inv
+            .iter()
+            .map(|s| (s.item.whoami().0, s.quantity))
+            .collect();
+        assert_eq!(order, vec![(0, 3), (0, 2), (1, 1), (9, 1)]);
+    }
+    #[test]
+    fn gold_test() {
+        let mut inv = Inventory::new();
+        inv.add_gold(50);
+        assert!(!inv.spend_gold(60));
+        assert!(inv.spend_gold(20));
+        assert_eq!(inv.get_gold(), 30);
+    }
+    #[test]
+    fn serde_test() {
+        let mut inv = Inventory::with_capacity(8);
+        inv.add(potion(), 2).unwrap();
+        inv.add_gold(12);
+        let json = serde_json::to_string(&inv).unwrap();
+        assert_eq!(serde_json::from_str::<Inventory>(&json).unwrap(), inv);
+    }
+}
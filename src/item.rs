@@ -1,12 +1,18 @@
+use rand::Rng;
 use std::collections::HashMap;
+use std::error::Error;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use crate::action::Scope;
+use crate::affinity::Affinities;
 use crate::common::*;
 use crate::effect::Effect;
+use crate::reaction::Reaction;
+use crate::stats::{Stat, StatMod, StatMods};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Item {
     id: Id,
     #[serde(default)]
@@ -17,10 +23,75 @@ pub struct Item {
     pub stamina_mod: i32,
     #[serde(default)]
     pub strength_mod: i32,
-    effect: IndexedOrLiteral<Effect>,
+    #[serde(default)]
+    pub mods: StatMods,
+    // Equipment slots the Item fits in; empty if it cannot be equipped
+    #[serde(default)]
+    pub slots: Vec<Name>,
+    // Classes allowed to equip the Item; empty if anyone can
+    #[serde(default)]
+    pub classes: Vec<Name>,
+    #[serde(default)]
+    effect: Option<IndexedOrLiteral<Effect>>,
+    #[serde(default = "Item::default_scope")]
+    pub scope: Scope,
+    #[serde(default = "Item::default_max_stack")]
+    pub max_stack: u16,
+    // Key items can't be discarded or used in battle
+    #[serde(default)]
+    pub key: bool,
+    // Reactions while equipped
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    // Affinities while equipped, e.g. {"Fire": "Absorb"}
+    #[serde(default)]
+    pub affinities: Affinities,
 }
 
 impl Item {
+    fn default_scope() -> Scope {
+        Scope::Ally
+    }
+    fn default_max_stack() -> u16 {
+        99
+    }
+    pub fn from_json(data: &str) -> Item {
+        let i: Item = serde_json::from_str(data).expect("Item JSON was not well-formatted");
+        i
+    }
+    pub fn whoami(&self) -> (Id, &str) {
+        (self.id, &self.name[..])
+    }
+    pub fn copy_name(&self) -> Name {
+        self.name.clone()
+    }
+    // Items without an Effect (e.g. equipment) can't be used
+    pub fn is_usable(&self) -> bool {
+        self.effect.is_some() && !self.key
+    }
+    pub fn get_effect(&self) -> Option<&IndexedOrLiteral<Effect>> {
+        self.effect.as_ref()
+    }
+    pub fn fits_slot(&self, slot: &Name) -> bool {
+        self.slots.contains(slot)
+    }
+    pub fn allows_class(&self, class: Option<&Name>) -> bool {
+        self.classes.is_empty() || class.is_some_and(|c| self.classes.contains(c))
+    }
+    pub fn get_attr(&self, attr: &Name) -> Option<Stat> {
+        if attr.eq_ignore_ascii_case("Power") {
+            return Some(self.power);
+        }
+        self.get_stat_mod(attr).map(|m| m.add)
+    }
+    pub fn get_stat_mod(&self, name: &Name) -> Option<StatMod> {
+        let legacy = self.get_modifier(name);
+        match self.mods.get(name) {
+            Some(m) => Some(StatMod::new(m.add + legacy, m.mult)),
+            None if legacy != 0 => Some(StatMod::new(legacy, StatMod::default().mult)),
+            None => None,
+        }
+    }
     pub fn get_modifier(&self, name: &Name) -> i32 {
         match name.as_str() {
             "Strength" => self.strength_mod,
@@ -53,13 +124,76 @@ pub fn equipment_mod(equipment: &EquipmentSlot, name: &Name) -> i32 {
     }
 }
 
+/// An entry in a drop or steal table
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ItemDrop {
+    pub item: Id,
+    // Percent chance of getting the Item
+    #[serde(default = "ItemDrop::default_chance")]
+    pub chance: i32,
+    #[serde(default = "ItemDrop::default_quantity")]
+    pub quantity: u16,
+}
+pub type DropTable = Vec<ItemDrop>;
+
+impl ItemDrop {
+    fn default_chance() -> i32 {
+        100
+    }
+    fn default_quantity() -> u16 {
+        1
+    }
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> bool {
+        rng.gen_range(0..100) < self.chance
+    }
+}
+
+pub fn roll_drops<R: Rng>(table: &DropTable, rng: &mut R) -> Vec<(Id, u16)> {
+    table
+        .iter()
+        .filter(|d| d.roll(rng))
+        .map(|d| (d.item, d.quantity))
+        .collect()
+}
+
 pub fn generate_equipment_set() -> EquipmentSet {
     let mut equips = EquipmentSet::new();
     equips.insert(String::from("Weapon"), None::<Item>);
     equips.insert(String::from("Shield"), None::<Item>);
+    equips.insert(String::from("Armor"), None::<Item>);
+    equips.insert(String::from("Relic"), None::<Item>);
     equips
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EquipError {
+    NoSuchSlot(Name),
+    WrongSlot { item: Name, slot: Name },
+    WrongClass { item: Name },
+    NoSuchItem(Id),
+    NoSuchCharacter(usize),
+    InventoryFull,
+}
+
+impl fmt::Display for EquipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EquipError::NoSuchSlot(slot) => write!(f, "there is no {slot} slot"),
+            EquipError::WrongSlot { item, slot } => {
+                write!(f, "{item} cannot be equipped as a {slot}")
+            }
+            EquipError::WrongClass { item } => write!(f, "{item} cannot be equipped by this class"),
+            EquipError::NoSuchItem(i) => write!(f, "there is no item {i} in the inventory"),
+            EquipError::NoSuchCharacter(pos) => {
+                write!(f, "there is no character at position {pos}")
+            }
+            EquipError::InventoryFull => write!(f, "the inventory is full"),
+        }
+    }
+}
+
+impl Error for EquipError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,7 +207,15 @@ mod tests {
             power: 1,
             strength_mod: -1,
             stamina_mod: -2,
-            effect: IndexedOrLiteral::<Effect>::Index(2), // Attack
+            mods: StatMods::new(),
+            slots: vec![String::from("Weapon")],
+            classes: Vec::new(),
+            effect: Some(IndexedOrLiteral::<Effect>::Index(2)), // Attack
+            scope: Scope::Enemy,
+            max_stack: 1,
+            key: false,
+            reactions: Vec::new(),
+            affinities: Affinities::new(),
         });
         assert_eq!(equipment_power(&item), 1);
         assert_eq!(equipment_power(&empty), 0);
@@ -84,4 +226,51 @@ mod tests {
         assert_eq!(equipment_mod(&item, &String::from("Moxie")), 0);
         assert_eq!(equipment_mod(&empty, &String::from("Moxie")), 0);
     }
+    #[test]
+    fn restrictions_test() {
+        let item = Item::from_json(
+            r#"{
+                "id": 30,
+                "name": "Mythril Spear",
+                "power": 60,
+                "mods": {"Speed": {"MultMod": 110}},
+                "slots": ["Weapon"],
+                "classes": ["Dragoon"]
+            }"#,
+        );
+        assert!(item.fits_slot(&String::from("Weapon")));
+        assert!(!item.fits_slot(&String::from("Shield")));
+        assert!(item.allows_class(Some(&String::from("Dragoon"))));
+        assert!(!item.allows_class(Some(&String::from("Moogle"))));
+        assert!(!item.allows_class(None));
+        assert_eq!(item.get_attr(&String::from("Power")), Some(60));
+        assert_eq!(item.get_attr(&String::from("power")), Some(60));
+        assert_eq!(item.get_attr(&String::from("Speed")), Some(0));
+        assert_eq!(item.get_attr(&String::from("Moxie")), None);
+        assert_eq!(
+            item.get_stat_mod(&String::from("Speed")),
+            Some(StatMod::new(0, 110))
+        );
+        let anyone = Item::from_json(r#"{"id": 31, "name": "Buckler", "slots": ["Shield"]}"#);
+        assert!(anyone.allows_class(None));
+    }
+    #[test]
+    fn roll_drops_test() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let table: DropTable = serde_json::from_str(
+            r#"[{"item": 0, "quantity": 2}, {"item": 1, "chance": 0}, {"item": 2, "chance": 50}]"#,
+        )
+        .unwrap();
+        assert_eq!(table[0].chance, 100);
+        assert_eq!(table[1].quantity, 1);
+        let mut rng = StdRng::seed_from_u64(11);
+        let drops = roll_drops(&table, &mut rng);
+        assert_eq!(drops[0], (0, 2));
+        assert!(!drops.iter().any(|d| d.0 == 1));
+        // The same seed rolls the same drops
+        let mut rng = StdRng::seed_from_u64(11);
+        assert_eq!(roll_drops(&table, &mut rng), drops);
+    }
 }
@@ -1,4 +1,6 @@
 pub mod action;
+pub mod affinity;
+pub mod ai;
 pub mod battle;
 pub mod battlecli;
 pub mod character;
@@ -7,9 +9,11 @@ pub mod condition;
 pub mod effect;
 pub mod encyclopedia;
 pub mod formula;
+pub mod inventory;
 pub mod item;
 pub mod map;
 pub mod party;
+pub mod reaction;
 pub mod scene;
 pub mod scenecli;
 pub mod sprite;
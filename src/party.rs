@@ -1,23 +1,29 @@
 use core::slice::Iter;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::character::Character;
 use crate::common::*;
+use crate::condition::ConditionTriggers;
 use crate::encyclopedia::CharacterEncyclopedia;
 use crate::encyclopedia::ConditionEncyclopedia;
+use crate::encyclopedia::ItemEncyclopedia;
 use crate::encyclopedia::StatBlockEncyclopedia;
-use crate::item::Item;
+use crate::inventory::{Gold, Inventory, InventoryError, Quantity, Stack};
+use crate::item::{self, EquipError, Item};
+use crate::stats::{Experience, Stat};
 
 type Group = Vec<Character>;
 type Ordering = Vec<usize>;
-type ItemPool = Vec<Item>;
 type Clocks = Vec<u16>; // Following FFVI
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Party {
     id: Id,
     name: Name,
     group: Group,
     formation: Ordering,
-    items: ItemPool,
+    items: Inventory,
     pub clocks: Clocks,
 }
 
@@ -28,7 +34,7 @@ impl Party {
             name,
             group: Group::new(),
             formation: Ordering::new(),
-            items: ItemPool::new(),
+            items: Inventory::new(),
             clocks: Clocks::new(),
         }
     }
@@ -113,15 +119,140 @@ impl Party {
         dt: u16,
         conditions: &ConditionEncyclopedia,
         statblocks: &StatBlockEncyclopedia,
-    ) {
-        for (ch, clk) in self.group.iter_mut().zip(self.clocks.iter_mut()) {
-            let dclk = ch.dclock(dt, conditions, statblocks);
-            *clk = clk.saturating_add(dclk);
+    ) -> Vec<(usize, ConditionTriggers)> {
+        self.fill_clocks(dt, statblocks);
+        self.experience_conditions(dt, conditions)
+    }
+    pub fn fill_clocks(&mut self, dt: u16, statblocks: &StatBlockEncyclopedia) {
+        for (ch, clk) in self.group.iter().zip(self.clocks.iter_mut()) {
+            *clk = clk.saturating_add(ch.dclock(dt, statblocks));
+        }
+    }
+    pub fn experience_conditions(
+        &mut self,
+        dt: u16,
+        conditions: &ConditionEncyclopedia,
+    ) -> Vec<(usize, ConditionTriggers)> {
+        let mut triggered = Vec::<(usize, ConditionTriggers)>::new();
+        for (i, ch) in self.group.iter_mut().enumerate() {
+            let triggers = ch.experience_conditions(dt, conditions);
+            if !triggers.is_empty() {
+                triggered.push((i, triggers));
+            }
         }
+        triggered
     }
-    pub fn items_iter(&self) -> Iter<Item> {
+    pub fn items_iter(&self) -> Iter<Stack> {
         self.items.iter()
     }
+    pub fn get_inventory(&self) -> &Inventory {
+        &self.items
+    }
+    pub fn get_mut_inventory(&mut self) -> &mut Inventory {
+        &mut self.items
+    }
+    pub fn add_item(&mut self, item: Item, quantity: Quantity) -> Result<(), InventoryError> {
+        self.items.add(item, quantity)
+    }
+    /// Moves the Items the Characters start with into the Party's inventory
+    pub fn stock_items(&mut self, item_enc: &ItemEncyclopedia) {
+        for ch in self.group.iter_mut() {
+            for id in ch.take_items() {
+                let item = item_enc
+                    .clone_entry(&IndexedOrLiteral::Index(id))
+                    .expect("missing item in encyclopedia");
+                self.items.add(item, 1).expect("party inventory is full");
+            }
+        }
+    }
+    pub fn get_item_stacks(&self) -> Vec<(&Item, Quantity)> {
+        self.items.iter().map(|s| (&s.item, s.quantity)).collect()
+    }
+    pub fn take_item(&mut self, id: Id) -> Option<Item> {
+        self.items.take(id)
+    }
+    /// Moves an Item from the inventory into a Character's slot, and
+    /// whatever was in the slot back into the inventory.
+    pub fn equip(&mut self, ch_pos: usize, item_id: Id, slot: Name) -> Result<(), EquipError> {
+        let ch = match self.formation.get(ch_pos) {
+            Some(i) => &mut self.group[*i],
+            None => return Err(EquipError::NoSuchCharacter(ch_pos)),
+        };
+        let item = self
+            .items
+            .get(item_id)
+            .ok_or(EquipError::NoSuchItem(item_id))?;
+        ch.can_equip(item, &slot)?;
+        if let Some(prev) = ch.get_equipped(&slot) {
+            // The swapped out Item needs somewhere to go
+            if self.items.count(item_id) > 1 && !self.items.has_room_for(prev, 1) {
+                return Err(EquipError::InventoryFull);
+            }
+        }
+        let item = self.items.take(item_id).unwrap();
+        match ch.equip_to_slot(item, slot) {
+            Ok(Some(prev)) => self.items.add(prev, 1).unwrap(),
+            Ok(None) => (),
+            Err((e, item)) => {
+                self.items.add(*item, 1).unwrap();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    pub fn unequip(&mut self, ch_pos: usize, slot: Name) -> Result<(), EquipError> {
+        let ch = match self.formation.get(ch_pos) {
+            Some(i) => &mut self.group[*i],
+            None => return Err(EquipError::NoSuchCharacter(ch_pos)),
+        };
+        if let Some(prev) = ch.get_equipped(&slot) {
+            if !self.items.has_room_for(prev, 1) {
+                return Err(EquipError::InventoryFull);
+            }
+        }
+        if let Some(prev) = ch.unequip_from_slot(slot)? {
+            self.items.add(prev, 1).unwrap();
+        }
+        Ok(())
+    }
+    pub fn get_experience_reward(&self) -> Experience {
+        self.group
+            .iter()
+            .fold(0, |xp, ch| xp.saturating_add(ch.experience_reward))
+    }
+    /// Splits the experience evenly between Characters who are still up.
+    /// Returns each one's position, share, and number of levels gained.
+    pub fn share_experience(
+        &mut self,
+        xp: Experience,
+        statblocks: &StatBlockEncyclopedia,
+    ) -> Vec<(usize, Experience, Stat)> {
+        let n = self.get_num_up() as Experience;
+        if n == 0 {
+            return Vec::new();
+        }
+        let share = xp / n;
+        let mut shared = Vec::<(usize, Experience, Stat)>::new();
+        for (pos, i) in self.formation.iter().enumerate() {
+            let ch = &mut self.group[*i];
+            if !ch.is_down() {
+                shared.push((pos, share, ch.gain_experience(share, statblocks)));
+            }
+        }
+        shared
+    }
+    pub fn get_gold_reward(&self) -> Gold {
+        self.group
+            .iter()
+            .fold(0, |gold, ch| gold.saturating_add(ch.gold))
+    }
+    pub fn roll_drops<R: Rng>(&self, rng: &mut R) -> Vec<(Id, Quantity)> {
+        let mut drops = Vec::<(Id, Quantity)>::new();
+        for ch in self.group.iter() {
+            drops.append(&mut item::roll_drops(&ch.drops, rng));
+        }
+        drops
+    }
     pub fn all_down(&self) -> bool {
         for ch in self.group.iter() {
             if !ch.is_down() {
@@ -180,4 +311,124 @@ mod tests {
         );
         assert!(matches!(party.get_ready_ch_pos(), Some(_)));
     }
+    #[test]
+    fn item_stacks_test() {
+        let mut party = Party::new(String::from("Test"));
+        party.add_clone(
+            &IndexedOrLiteral::Index(10), // Mog
+            &CharacterEncyclopedia::new("data/characters.json"),
+        );
+        party.stock_items(&ItemEncyclopedia::new("data/items.json"));
+        let stacks = party.get_item_stacks();
+        assert_eq!(stacks.len(), 2);
+        assert_eq!((stacks[0].0.whoami(), stacks[0].1), ((0, "Potion"), 2));
+        assert_eq!((stacks[1].0.whoami(), stacks[1].1), ((1, "Elixer"), 1));
+        // Starting Items only get stocked once
+        party.stock_items(&ItemEncyclopedia::new("data/items.json"));
+        assert_eq!(party.items.count(0), 2);
+        assert!(party.take_item(0).is_some());
+        assert_eq!(party.get_item_stacks()[0].1, 1);
+        assert!(party.take_item(0).is_some());
+        assert!(party.take_item(0).is_none());
+        assert_eq!(party.get_item_stacks().len(), 1);
+    }
+    #[test]
+    fn share_experience_test() {
+        let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+        let mut party = Party::new(String::from("Test"));
+        party.add_character(Character::new(0, String::from("Mog")));
+        party.add_character(Character::new(1, String::from("Gau")));
+        party.add_character(Character::new(2, String::from("Umaro")));
+        party
+            .get_mut_ch_by_pos(1)
+            .unwrap()
+            .hit_pool(&String::from("HP"), 9999);
+        let shared = party.share_experience(65, &statblocks);
+        assert_eq!(shared.len(), 2);
+        assert_eq!((shared[0].0, shared[0].1), (0, 32));
+        assert_eq!((shared[1].0, shared[1].1), (2, 32));
+        assert_eq!(party.get_ch_by_pos(1).unwrap().get_experience(), 0);
+        assert_eq!(party.get_experience_reward(), 0);
+    }
+    #[test]
+    fn loot_test() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut baddies = Party::new(String::from("Test"));
+        let ch_enc = CharacterEncyclopedia::new("data/characters.json");
+        baddies.add_clone(&IndexedOrLiteral::Index(101), &ch_enc);
+        baddies.add_clone(&IndexedOrLiteral::Index(101), &ch_enc);
+        assert_eq!(
+            baddies.get_gold_reward(),
+            2 * ch_enc.get(&101).unwrap().gold
+        );
+        let drops = baddies.roll_drops(&mut StdRng::seed_from_u64(0));
+        assert_eq!(drops, baddies.roll_drops(&mut StdRng::seed_from_u64(0)));
+    }
+    #[test]
+    fn equip_test() {
+        let mut party = Party::new(String::from("Test"));
+        party.add_character(Character::new(0, String::from("Mog")));
+        let spear =
+            Item::from_json(r#"{"id": 1, "name": "Spear", "power": 6, "slots": ["Weapon"]}"#);
+        let buckler =
+            Item::from_json(r#"{"id": 2, "name": "Buckler", "power": 7, "slots": ["Shield"]}"#);
+        party.add_item(spear, 1).unwrap();
+        party.add_item(buckler, 1).unwrap();
+        assert_eq!(
+            party.equip(0, 2, String::from("Weapon")),
+            Err(EquipError::WrongSlot {
+                item: String::from("Buckler"),
+                slot: String::from("Weapon")
+            })
+        );
+        assert_eq!(party.items.len(), 2);
+        assert_eq!(
+            party.equip(0, 3, String::from("Weapon")),
+            Err(EquipError::NoSuchItem(3))
+        );
+        assert_eq!(
+            party.equip(9, 1, String::from("Weapon")),
+            Err(EquipError::NoSuchCharacter(9))
+        );
+        assert_eq!(
+            party.unequip(9, String::from("Weapon")),
+            Err(EquipError::NoSuchCharacter(9))
+        );
+        assert_eq!(party.items.count(1), 1);
+        assert_eq!(party.equip(0, 1, String::from("Weapon")), Ok(()));
+        assert_eq!(party.items.len(), 1);
+        assert_eq!(party.items.count(1), 0);
+        let mog = party.get_ch_by_pos(0).unwrap();
+        assert_eq!(
+            mog.get_item_attr(String::from("Weapon"), String::from("Power")),
+            Some(6)
+        );
+        assert_eq!(party.unequip(0, String::from("Weapon")), Ok(()));
+        assert_eq!(party.items.count(1), 1);
+        assert_eq!(party.unequip(0, String::from("Weapon")), Ok(()));
+        assert_eq!(party.items.len(), 2);
+    }
+    #[test]
+    fn equip_full_inventory_test() {
+        let mut party = Party::new(String::from("Test"));
+        party.items = Inventory::with_capacity(1);
+        party.add_character(Character::new(0, String::from("Mog")));
+        let spear = Item::from_json(r#"{"id": 1, "name": "Spear", "slots": ["Weapon"]}"#);
+        party.add_item(spear, 2).unwrap();
+        party.equip(0, 1, String::from("Weapon")).unwrap();
+        let lance = Item::from_json(r#"{"id": 3, "name": "Lance", "slots": ["Weapon"]}"#);
+        assert_eq!(party.add_item(lance, 1), Err(InventoryError::Full));
+        // Swapping in the other Spear tops up the Spear stack
+        assert_eq!(party.equip(0, 1, String::from("Weapon")), Ok(()));
+        assert_eq!(party.items.count(1), 1);
+        party.items.remove(1, 1).unwrap();
+        let lance = Item::from_json(r#"{"id": 3, "name": "Lance", "slots": ["Weapon"]}"#);
+        party.add_item(lance, 1).unwrap();
+        assert_eq!(
+            party.unequip(0, String::from("Weapon")),
+            Err(EquipError::InventoryFull)
+        );
+    }
 }
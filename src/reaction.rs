@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ai::Check;
+use crate::common::*;
+use crate::effect::{Effect, Traits};
+
+/// What a Character does when a Reaction goes off
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum Response {
+    // An Effect back at whoever set it off, e.g. a counter-attack
+    Counter(IndexedOrLiteral<Effect>),
+    // The incoming Effect lands on whoever used it instead
+    Reflect,
+    // An Effect on the reactor themself, e.g. an auto-potion
+    Effect(IndexedOrLiteral<Effect>),
+}
+
+/// A response to incoming Effects, from a Condition or equipment.
+/// Effects played in reaction can't set off more reactions.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Reaction {
+    // Incoming Effects with any of these traits set it off; empty for any Effect
+    #[serde(default)]
+    pub traits: Traits,
+    // Percent chance of going off
+    #[serde(default = "Reaction::default_chance")]
+    pub chance: i32,
+    // Checked from the reactor's side; for anything but Reflect, once the
+    // incoming Effect has landed, e.g. SelfBelow for an auto-potion
+    #[serde(default)]
+    pub when: Vec<Check>,
+    pub response: Response,
+}
+
+impl Reaction {
+    fn default_chance() -> i32 {
+        100
+    }
+    pub fn is_set_off_by(&self, traits: &Traits) -> bool {
+        self.traits.is_empty() || self.traits.iter().any(|t| traits.contains(t))
+    }
+    pub fn is_reflect(&self) -> bool {
+        self.response == Response::Reflect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_off_test() {
+        let counter: Reaction = serde_json::from_str(
+            r#"{"traits": ["Physical"], "response": {"Counter": {"Index": 0}}}"#,
+        )
+        .unwrap();
+        assert_eq!(counter.chance, 100);
+        assert!(counter.is_set_off_by(&vec![String::from("Physical")]));
+        assert!(!counter.is_set_off_by(&vec![String::from("Magic")]));
+        assert!(!counter.is_set_off_by(&Traits::new()));
+        assert!(!counter.is_reflect());
+        let auto_potion: Reaction =
+            serde_json::from_str(r#"{"response": {"Effect": {"Index": 173000}}}"#).unwrap();
+        assert!(auto_potion.is_set_off_by(&Traits::new()));
+    }
+}
@@ -3,25 +3,148 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-use crate::common::{Formula, Id, Name};
+use crate::common::{Id, Name};
+use crate::encyclopedia::StatBlockEncyclopedia;
+use crate::formula::{FormulaError, HitFormula, StatFormula};
 
 pub type Stat = i32;
 pub type BaseStats = HashMap<Name, Stat>;
-pub type DerivedStat = Formula;
+pub type DerivedStat = StatFormula;
 pub type DerivedStats = HashMap<Name, DerivedStat>;
 
+/// A modifier picked up by the .AddMod and .MultMod terms of derived stat
+/// formulas. MultMod is a percentage, so 100 leaves a stat unchanged.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StatMod {
+    #[serde(default, rename = "AddMod")]
+    pub add: Stat,
+    #[serde(default = "StatMod::default_mult", rename = "MultMod")]
+    pub mult: Stat,
+}
+pub type StatMods = HashMap<Name, StatMod>;
+
+impl StatMod {
+    fn default_mult() -> Stat {
+        100
+    }
+    pub fn new(add: Stat, mult: Stat) -> StatMod {
+        StatMod { add, mult }
+    }
+    pub fn is_boost(&self) -> bool {
+        self.add > 0 || self.mult > 100
+    }
+}
+
+impl Default for StatMod {
+    fn default() -> Self {
+        StatMod::new(0, StatMod::default_mult())
+    }
+}
+
+pub fn sum_add_mods<'a>(mods: impl Iterator<Item = &'a StatMod>) -> Stat {
+    mods.fold(0, |sum, m| sum.saturating_add(m.add))
+}
+
+pub fn sum_mult_mods<'a>(mods: impl Iterator<Item = &'a StatMod>) -> Stat {
+    mods.fold(StatMod::default_mult(), |product, m| {
+        product.saturating_mul(m.mult) / StatMod::default_mult()
+    })
+}
+
+/// A temporary StatMod, e.g. from a spell
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Buff {
+    pub stat: Name,
+    #[serde(flatten)]
+    pub mods: StatMod,
+    pub duration: u16,
+}
+pub type Buffs = Vec<Buff>;
+
+pub type Experience = u32;
+
+/// How a Character grows when they level up
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct Growth {
+    // Total experience needed for levels 2, 3, and so on
+    #[serde(default)]
+    pub thresholds: Vec<Experience>,
+    // Base stats gained each level
+    #[serde(default)]
+    pub stats: HashMap<Name, Stat>,
+    // Pool maximums gained each level
+    #[serde(default)]
+    pub pools: HashMap<Name, i32>,
+}
+
+impl Growth {
+    pub fn next_level_at(&self, level: Stat) -> Option<Experience> {
+        let i = usize::try_from(level - 1).ok()?;
+        self.thresholds.get(i).copied()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StatBlock {
     id: Id,
     name: Name,
     base_stats: BaseStats,
     stats: DerivedStats,
+    // Pools the Characters have, e.g. HP
+    #[serde(default)]
+    pools: Vec<Name>,
+    #[serde(default)]
+    growth: Option<Growth>,
+    // Percent chance of running from a Battle, with the fastest enemy as target
+    #[serde(default)]
+    escape: Option<HitFormula>,
 }
 
 impl StatBlock {
     pub fn get_stat(&self, name: Name) -> Option<&DerivedStat> {
         self.stats.get(&name)
     }
+    pub fn get_growth(&self) -> Option<&Growth> {
+        self.growth.as_ref()
+    }
+    pub fn get_escape(&self) -> Option<&HitFormula> {
+        self.escape.as_ref()
+    }
+    pub fn has_base_stat(&self, name: &Name) -> bool {
+        self.base_stats.contains_key(name)
+    }
+    pub fn has_stat(&self, name: &Name) -> bool {
+        self.has_base_stat(name) || self.stats.contains_key(name)
+    }
+    pub fn has_pool(&self, name: &Name) -> bool {
+        self.pools.contains(name)
+    }
+    /// Checks that the formulas only refer to declared stats and pools
+    pub fn check_stats(&self, statblocks: &StatBlockEncyclopedia) -> Result<(), FormulaError> {
+        for (name, formula) in &self.stats {
+            formula.check_stats(name, self)?;
+        }
+        match &self.escape {
+            Some(escape) => escape.check_stats(statblocks),
+            None => Ok(()),
+        }
+    }
+}
+
+impl StatBlockEncyclopedia {
+    pub fn has_stat(&self, name: &Name) -> bool {
+        self.en.values().any(|sb| sb.has_stat(name))
+    }
+    pub fn has_pool(&self, name: &Name) -> bool {
+        self.en.values().any(|sb| sb.has_pool(name))
+    }
+    /// Hands back the first StatBlock with a formula that refers to an undeclared stat
+    pub fn check_stats(&self) -> Result<(), (Id, FormulaError)> {
+        for (id, sb) in &self.en {
+            sb.check_stats(self).map_err(|e| (*id, e))?;
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for StatBlock {
@@ -39,3 +162,43 @@ impl fmt::Display for StatBlock {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_mod_test() {
+        let m: StatMod = serde_json::from_str(r#"{"AddMod": 5}"#).unwrap();
+        assert_eq!(m, StatMod::new(5, 100));
+        let m: StatMod = serde_json::from_str(r#"{"MultMod": 50}"#).unwrap();
+        assert_eq!(m, StatMod::new(0, 50));
+        assert!(!m.is_boost());
+        let mods = [
+            StatMod::new(5, 200),
+            StatMod::new(-2, 50),
+            StatMod::default(),
+        ];
+        assert_eq!(sum_add_mods(mods.iter()), 3);
+        assert_eq!(sum_mult_mods(mods.iter()), 100);
+        assert_eq!(sum_add_mods(Vec::<StatMod>::new().iter()), 0);
+        assert_eq!(sum_mult_mods(Vec::<StatMod>::new().iter()), 100);
+    }
+    #[test]
+    fn buff_test() {
+        let b: Buff =
+            serde_json::from_str(r#"{"stat": "Speed", "MultMod": 150, "duration": 4096}"#).unwrap();
+        assert_eq!(b.mods, StatMod::new(0, 150));
+    }
+    #[test]
+    fn growth_test() {
+        let g: Growth = serde_json::from_str(
+            r#"{"thresholds": [10, 30], "stats": {"Strength": 2}, "pools": {"HP": 10}}"#,
+        )
+        .unwrap();
+        assert_eq!(g.next_level_at(1), Some(10));
+        assert_eq!(g.next_level_at(2), Some(30));
+        assert_eq!(g.next_level_at(3), None);
+        assert_eq!(g.next_level_at(0), None);
+    }
+}
@@ -44,7 +44,13 @@ fn use_actions() {
         .unwrap();
     let mog_mp = mog.get_pool_vals(String::from("MP")).unwrap().0;
     let rat_hp = rat.get_pool_vals(String::from("HP")).unwrap().0;
-    let hits = mog.use_action_on(test_act_0, &rat, &effect_enc, &statblocks);
+    let hits = mog.use_action_on(
+        test_act_0,
+        &rat,
+        &effect_enc,
+        &statblocks,
+        &mut rand::thread_rng(),
+    );
     for hit in &hits {
         if let HitAmt::Constant(amt) = hit.amount {
             rat.hit_pool(&hit.pool, amt);
@@ -67,43 +73,66 @@ fn use_actions() {
     assert_eq!(rat.get_pool_vals(String::from("MP")), None);
 }
 
-/*
 #[test]
 fn equip_mog() {
-    let mut mog = rpgrs::character::Character::new(
-        0,
-        String::from("Mog"),
-    );
-    let nude_offense = mog.get_stat(String::from("Offense"));
-    let spear_power = 6;
-    let spear = rpgrs::item::create(
-        String::from("Spear"),
-        spear_power,
-        0,
-        0,
-    );
+    let characters = CharacterEncyclopedia::new("data/characters.json");
+    let items = ItemEncyclopedia::new("data/items.json");
+    let statblocks = StatBlockEncyclopedia::new("data/stats.json");
+    let mut party = rpgrs::party::Party::new(String::from("Moogles"));
+    party.add_clone(&IndexedOrLiteral::Index(10), &characters);
+    for id in [100, 101, 102] {
+        let item = items.clone_entry(&IndexedOrLiteral::Index(id)).unwrap();
+        party.add_item(item, 1).unwrap();
+    }
+    let stat = |party: &rpgrs::party::Party, name: &str| {
+        party
+            .get_ch_by_pos(0)
+            .unwrap()
+            .get_stat_val(String::from(name), 0, &statblocks)
+    };
+    let nude_offense = stat(&party, "Offense");
+    let nude_defense = stat(&party, "Defense");
 
-    let prev_weapon = mog.equip_to_slot(spear, String::from("Weapon"));
-    assert!(prev_weapon.is_none());
-    assert_eq!(mog.get_stat(String::from("Offense")), nude_offense + spear_power);
+    party.equip(0, 100, String::from("Weapon")).unwrap(); // Mythril Spear
+    assert_eq!(stat(&party, "Offense"), nude_offense + 6);
+    party.equip(0, 101, String::from("Shield")).unwrap(); // Buckler
+    party.equip(0, 102, String::from("Armor")).unwrap(); // Leather Armor
+    assert_eq!(stat(&party, "Defense"), nude_defense + 7 + 8);
+    assert_eq!(party.items_iter().count(), 0);
 
-    let nude_defense = mog.get_stat(String::from("Defense"));
-    let shield_power = 7;
-    let shield = rpgrs::item::create(
-        String::from("Shield"),
-        shield_power,
-        0,
-        0,
-    );
-    let prev_shield = mog.equip_to_slot(shield, String::from("Shield"));
-    assert!(prev_shield.is_none());
-    assert_eq!(mog.get_stat(String::from("Defense")), nude_defense + shield_power);
+    party.unequip(0, String::from("Weapon")).unwrap();
+    party.unequip(0, String::from("Shield")).unwrap();
+    party.unequip(0, String::from("Armor")).unwrap();
+    assert_eq!(party.items_iter().count(), 3);
+    assert_eq!(stat(&party, "Offense"), nude_offense);
+    assert_eq!(stat(&party, "Defense"), nude_defense);
+}
 
-    let prev_weapon = mog.unequip_from_slot(String::from("Weapon"));
-    assert!(prev_weapon.is_some());
-    let prev_shield = mog.unequip_from_slot(String::from("Shield"));
-    assert!(prev_shield.is_some());
-    assert_eq!(mog.get_stat(String::from("Offense")), nude_offense);
-    assert_eq!(mog.get_stat(String::from("Defense")), nude_defense);
+#[test]
+fn scripted_battle() {
+    use rpgrs::battle::event::BattleEvent;
+    use rpgrs::battle::headless::HeadlessBattleUI;
+    use rpgrs::battle::{Battle, BattleOutcome};
+    let characters = CharacterEncyclopedia::new("data/characters.json");
+    let mut allies = rpgrs::party::Party::new(String::from("Allies"));
+    let mut baddies = rpgrs::party::Party::new(String::from("Baddies"));
+    allies.add_clone(&IndexedOrLiteral::Index(10), &characters); // Mog
+    baddies.add_clone(&IndexedOrLiteral::Index(101), &characters); // Mouse-Sized Rat
+    let battle = Battle::with_seed(allies, baddies, 1);
+    let script = "select Item, select Potion, target Ally(0),\n".to_string()
+        + &"select Attack, target Baddy(0),\n".repeat(20);
+    let mut ui = HeadlessBattleUI::from_script(&script).unwrap();
+    let result = battle.run(&mut ui);
+    assert_eq!(ui.get_error(), None);
+    let log = ui.get_log();
+    assert_eq!(log[0], "Battle start!");
+    assert!(log.contains(&String::from("Mog used Potion on Mog.")));
+    assert_eq!(result.outcome, BattleOutcome::Victory);
+    assert_eq!(log.last().unwrap(), "Found 12 gold!");
+    assert_eq!(ui.get_events().len(), log.len());
+    assert!(ui
+        .get_events()
+        .contains(&BattleEvent::BattleEnded(BattleOutcome::Victory)));
+    assert!(ui.commands_left() > 0);
+    assert!(HeadlessBattleUI::from_script("dance wildly").is_err());
 }
-*/